@@ -1,5 +1,5 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use clap::{App, Arg, SubCommand};
+use easy_fs::{BlockDevice, EasyFileSystem, FsError, FsResult};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
@@ -10,18 +10,42 @@ const BLOCK_SZ: usize = 512;
 struct BlockFile(Mutex<File>);
 
 impl BlockDevice for BlockFile {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult {
         let mut file = self.0.lock().unwrap();
         file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
-            .expect("Error when seeking!");
-        assert_eq!(file.read(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
+            .and_then(|_| file.read_exact(buf))
+            .map_err(|_| FsError::Io)
     }
 
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult {
         let mut file = self.0.lock().unwrap();
         file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
-            .expect("Error when seeking!");
-        assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
+            .and_then(|_| file.write_all(buf))
+            .map_err(|_| FsError::Io)
+    }
+
+    fn flush(&self) -> FsResult {
+        let file = self.0.lock().unwrap();
+        file.sync_data().map_err(|_| FsError::Io)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn discard(&self, blocks: std::ops::Range<usize>) -> FsResult {
+        use std::os::unix::io::AsRawFd;
+        let file = self.0.lock().unwrap();
+        let ret = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                (blocks.start * BLOCK_SZ) as libc::off_t,
+                (blocks.len() * BLOCK_SZ) as libc::off_t,
+            )
+        };
+        // the host filesystem may not support holes, discarding is only a hint
+        if ret != 0 && std::io::Error::last_os_error().raw_os_error() != Some(libc::EOPNOTSUPP) {
+            return Err(FsError::Io);
+        }
+        Ok(())
     }
 
     fn handle_irq(&self) { unimplemented!(); }
@@ -47,7 +71,57 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .subcommand(
+            SubCommand::with_name("grow")
+                .about("Grow an existing image to a larger size")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path of fs.img"),
+                )
+                .arg(
+                    Arg::with_name("blocks")
+                        .short("b")
+                        .long("blocks")
+                        .takes_value(true)
+                        .required(true)
+                        .help("New total blocks of the image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("format")
+                .about("Create an empty image, e.g. a scratch disk")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path of the new image"),
+                )
+                .arg(
+                    Arg::with_name("blocks")
+                        .short("b")
+                        .long("blocks")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Total blocks of the image"),
+                ),
+        )
         .get_matches();
+    if let Some(matches) = matches.subcommand_matches("format") {
+        let image_path = matches.value_of("image").unwrap();
+        let total_blocks: u32 = matches.value_of("blocks").unwrap().parse().unwrap();
+        return easy_fs_format(image_path, total_blocks);
+    }
+    if let Some(matches) = matches.subcommand_matches("grow") {
+        let image_path = matches.value_of("image").unwrap();
+        let total_blocks: u32 = matches.value_of("blocks").unwrap().parse().unwrap();
+        return easy_fs_grow(image_path, total_blocks);
+    }
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     println!("src_path = {}\ntarget_path = {}", src_path, target_path);
@@ -61,8 +135,8 @@ fn easy_fs_pack() -> std::io::Result<()> {
         f
     })));
     // 16MiB, at most 4095 files
-    let efs = EasyFileSystem::create(block_file, 16 * 2048, 1);
-    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
+    let efs = EasyFileSystem::create(block_file, 16 * 2048, 1).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
     let apps: Vec<_> = read_dir(src_path)
         .unwrap()
         .into_iter()
@@ -78,17 +152,52 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let mut all_data: Vec<u8> = Vec::new();
         host_file.read_to_end(&mut all_data).unwrap();
         // create a file in easy-fs
-        let inode = root_inode.create(app.as_str()).unwrap();
+        let inode = root_inode.create(app.as_str()).unwrap().unwrap();
         // write data to easy-fs
-        inode.write_at(0, all_data.as_slice());
+        inode.write_at(0, all_data.as_slice()).unwrap();
     }
+    // empty directories to mount other disks, devfs, procfs and tmpfs on
+    root_inode.mkdir("mnt").unwrap();
+    root_inode.mkdir("dev").unwrap();
+    root_inode.mkdir("proc").unwrap();
+    root_inode.mkdir("tmp").unwrap();
     // list apps
-    for app in root_inode.ls() {
+    for app in root_inode.ls().unwrap() {
         println!("{}", app);
     }
     Ok(())
 }
 
+fn easy_fs_format(image_path: &str, total_blocks: u32) -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(image_path)?;
+        f.set_len(total_blocks as u64 * BLOCK_SZ as u64)?;
+        f
+    })));
+    EasyFileSystem::create(block_file, total_blocks, 1).expect("Error creating EFS!");
+    println!("{} formatted with {} blocks", image_path, total_blocks);
+    Ok(())
+}
+
+fn easy_fs_grow(image_path: &str, total_blocks: u32) -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new().read(true).write(true).open(image_path)?;
+        if f.metadata()?.len() < total_blocks as u64 * BLOCK_SZ as u64 {
+            f.set_len(total_blocks as u64 * BLOCK_SZ as u64)?;
+        }
+        f
+    })));
+    let efs = EasyFileSystem::open(block_file).expect("Error loading EFS!");
+    EasyFileSystem::grow(&efs, total_blocks).expect("Error growing EFS!");
+    println!("{} grown to {} blocks", image_path, total_blocks);
+    Ok(())
+}
+
 #[test]
 fn efs_test() -> std::io::Result<()> {
     let block_file = Arc::new(BlockFile(Mutex::new({
@@ -100,37 +209,48 @@ fn efs_test() -> std::io::Result<()> {
         f.set_len(8192 * 512).unwrap();
         f
     })));
-    EasyFileSystem::create(block_file.clone(), 4096, 1);
-    let efs = EasyFileSystem::open(block_file.clone());
+    EasyFileSystem::create(block_file.clone(), 4096, 1).unwrap();
+    let efs = EasyFileSystem::open(block_file.clone()).unwrap();
     let root_inode = EasyFileSystem::root_inode(&efs);
-    root_inode.create("filea");
-    root_inode.create("fileb");
-    for name in root_inode.ls() {
+    root_inode.create("filea").unwrap();
+    root_inode.create("fileb").unwrap();
+    for name in root_inode.ls().unwrap() {
         println!("{}", name);
     }
-    let filea = root_inode.find("filea").unwrap();
+    let filea = root_inode.find("filea").unwrap().unwrap();
+    assert!(Arc::ptr_eq(
+        &filea,
+        &root_inode.find("filea").unwrap().unwrap()
+    ));
+    assert!(filea
+        .set_xattr("user.origin", b"efs_test", easy_fs::XattrMode::Create)
+        .unwrap());
+    assert_eq!(filea.get_xattr("user.origin").unwrap(), b"efs_test");
+    assert_eq!(filea.list_xattr().unwrap(), ["user.origin"]);
+    filea.remove_xattr("user.origin").unwrap();
+    assert_eq!(filea.get_xattr("user.origin"), Err(FsError::NotFound));
     let greet_str = "Hello, world!";
-    filea.write_at(0, greet_str.as_bytes());
+    filea.write_at(0, greet_str.as_bytes()).unwrap();
     //let mut buffer = [0u8; 512];
     let mut buffer = [0u8; 233];
-    let len = filea.read_at(0, &mut buffer);
+    let len = filea.read_at(0, &mut buffer).unwrap();
     assert_eq!(greet_str, core::str::from_utf8(&buffer[..len]).unwrap(),);
 
     let mut random_str_test = |len: usize| {
-        filea.clear();
-        assert_eq!(filea.read_at(0, &mut buffer), 0,);
+        filea.clear().unwrap();
+        assert_eq!(filea.read_at(0, &mut buffer).unwrap(), 0,);
         let mut str = String::new();
         use rand;
         // random digit
         for _ in 0..len {
             str.push(char::from('0' as u8 + rand::random::<u8>() % 10));
         }
-        filea.write_at(0, str.as_bytes());
+        filea.write_at(0, str.as_bytes()).unwrap();
         let mut read_buffer = [0u8; 127];
         let mut offset = 0usize;
         let mut read_str = String::new();
         loop {
-            let len = filea.read_at(offset, &mut read_buffer);
+            let len = filea.read_at(offset, &mut read_buffer).unwrap();
             if len == 0 {
                 break;
             }
@@ -149,5 +269,34 @@ fn efs_test() -> std::io::Result<()> {
     random_str_test(1000 * BLOCK_SZ);
     random_str_test(2000 * BLOCK_SZ);
 
+    // grow into the rest of the image, the data bitmap takes over the first data block
+    let mut data = vec![0u8; 2000 * BLOCK_SZ];
+    assert_eq!(filea.read_at(0, &mut data).unwrap(), data.len());
+    EasyFileSystem::grow(&efs, 8192).unwrap();
+    let mut grown_data = vec![0u8; 2000 * BLOCK_SZ];
+    assert_eq!(filea.read_at(0, &mut grown_data).unwrap(), data.len());
+    assert!(data == grown_data);
+    assert_eq!(root_inode.ls().unwrap(), ["filea", "fileb"]);
+    random_str_test(4000 * BLOCK_SZ);
+
+    let dir = root_inode.mkdir("dir").unwrap().unwrap();
+    assert!(dir.is_dir().unwrap());
+    assert!(root_inode.mkdir("dir").unwrap().is_none());
+    dir.create("filec").unwrap();
+    assert_eq!(dir.ls().unwrap(), ["filec"]);
+    assert_eq!(filea.ls(), Err(FsError::NotDir));
+    assert_eq!(filea.create("filed").err(), Some(FsError::NotDir));
+
+    // after unmounting, a fresh open sees everything
+    drop(root_inode);
+    drop(efs);
+    let block_file: Arc<dyn BlockDevice> = block_file;
+    EasyFileSystem::unmount(&block_file).unwrap();
+    let efs = EasyFileSystem::open(block_file).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.ls().unwrap(), ["filea", "fileb", "dir"]);
+    let dir = root_inode.find("dir").unwrap().unwrap();
+    assert_eq!(dir.ls().unwrap(), ["filec"]);
+
     Ok(())
 }
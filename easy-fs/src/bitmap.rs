@@ -1,9 +1,22 @@
-use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use super::{get_block_cache, BlockDevice, Checksummed, FsError, FsResult, BLOCK_SZ};
+use crate::checksum::crc32_of;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-type BitmapBlock = [u64; 64];
+/// Words are little-endian, the last u64 of each bitmap block holds its checksum.
+type BitmapBlock = [u64; BITMAP_WORDS + 1];
 
-const BLOCK_BITS: usize = BLOCK_SZ * 8;
+const BITMAP_WORDS: usize = BLOCK_SZ / 8 - 1;
+pub const BLOCK_BITS: usize = BITMAP_WORDS * 64;
+
+impl Checksummed for BitmapBlock {
+    fn checksum_ok(&self) -> bool {
+        u64::from_le(self[BITMAP_WORDS]) == crc32_of(self, BITMAP_WORDS * 8) as u64
+    }
+    fn update_checksum(&mut self) {
+        self[BITMAP_WORDS] = (crc32_of(self, BITMAP_WORDS * 8) as u64).to_le();
+    }
+}
 
 pub struct Bitmap {
     start_block_id: usize,
@@ -25,42 +38,93 @@ impl Bitmap {
         }
     }
 
-    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
+    /// Clear all bits and write the checksums.
+    pub fn initialize(&self, block_device: &Arc<dyn BlockDevice>) -> FsResult {
+        for block_id in 0..self.blocks {
+            get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))?
+                .lock()
+                .modify(0, |bitmap_block: &mut BitmapBlock| {
+                    bitmap_block.iter_mut().for_each(|bits64| *bits64 = 0);
+                    bitmap_block.update_checksum();
+                });
+        }
+        Ok(())
+    }
+
+    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> FsResult<Option<usize>> {
         for block_id in 0..self.blocks {
             let pos = get_block_cache(
                 block_id + self.start_block_id as usize,
                 Arc::clone(block_device),
-            )
+            )?
             .lock()
-            .modify(0, |bitmap_block: &mut BitmapBlock| {
+            .modify_checked(0, |bitmap_block: &mut BitmapBlock| {
                 if let Some((bits64_pos, inner_pos)) = bitmap_block
                     .iter()
+                    .take(BITMAP_WORDS)
                     .enumerate()
-                    .find(|(_, bits64)| **bits64 != u64::MAX)
+                    .map(|(bits64_pos, bits64)| (bits64_pos, u64::from_le(*bits64)))
+                    .find(|(_, bits64)| *bits64 != u64::MAX)
                     .map(|(bits64_pos, bits64)| (bits64_pos, bits64.trailing_ones() as usize))
                 {
                     // modify cache
-                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                    bitmap_block[bits64_pos] |= (1u64 << inner_pos).to_le();
                     Some(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos as usize)
                 } else {
                     None
                 }
-            });
+            })?;
             if pos.is_some() {
-                return pos;
+                return Ok(pos);
             }
         }
-        None
+        Ok(None)
+    }
+
+    /// Freeing a bit which is not allocated means the metadata is corrupted.
+    pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> FsResult {
+        if bit >= self.maximum() {
+            return Err(FsError::Corrupted);
+        }
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))?
+            .lock()
+            .modify_checked(0, |bitmap_block: &mut BitmapBlock| {
+                let mask = (1u64 << inner_pos).to_le();
+                if bitmap_block[bits64_pos] & mask == 0 {
+                    return Err(FsError::Corrupted);
+                }
+                bitmap_block[bits64_pos] &= !mask;
+                Ok(())
+            })?
     }
 
-    pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+    /// Mark `bit` as allocated.
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> FsResult {
         let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
-        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))?
             .lock()
-            .modify(0, |bitmap_block: &mut BitmapBlock| {
-                assert!(bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0);
-                bitmap_block[bits64_pos] -= 1u64 << inner_pos;
-            });
+            .modify_checked(0, |bitmap_block: &mut BitmapBlock| {
+                bitmap_block[bits64_pos] |= (1u64 << inner_pos).to_le();
+            })
+    }
+
+    /// Return all allocated bits in ascending order.
+    pub fn allocated(&self, block_device: &Arc<dyn BlockDevice>) -> FsResult<Vec<usize>> {
+        let mut v = Vec::new();
+        for block_id in 0..self.blocks {
+            get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))?
+                .lock()
+                .read_checked(0, |bitmap_block: &BitmapBlock| {
+                    for (bits64_pos, bits64) in bitmap_block.iter().take(BITMAP_WORDS).enumerate() {
+                        let bits64 = u64::from_le(*bits64);
+                        for inner_pos in (0..64).filter(|i| bits64 & (1u64 << i) != 0) {
+                            v.push(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos);
+                        }
+                    }
+                })?;
+        }
+        Ok(v)
     }
 
     pub fn maximum(&self) -> usize {
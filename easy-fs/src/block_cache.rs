@@ -1,6 +1,8 @@
-use super::{BlockDevice, BLOCK_SZ};
+use super::{BlockDevice, Checksummed, FsError, FsResult, BLOCK_SZ};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
 use lazy_static::*;
 use spin::Mutex;
 
@@ -13,15 +15,15 @@ pub struct BlockCache {
 
 impl BlockCache {
     /// Load a new BlockCache from disk.
-    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> FsResult<Self> {
         let mut cache = [0u8; BLOCK_SZ];
-        block_device.read_block(block_id, &mut cache);
-        Self {
+        block_device.read_block(block_id, &mut cache)?;
+        Ok(Self {
             cache,
             block_id,
             block_device,
             modified: false,
-        }
+        })
     }
 
     fn addr_of_offset(&self, offset: usize) -> usize {
@@ -57,24 +59,62 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
-    pub fn sync(&mut self) {
+    /// Like `read`, but verify the checksum of `T` first.
+    pub fn read_checked<T: Checksummed, V>(
+        &self,
+        offset: usize,
+        f: impl FnOnce(&T) -> V,
+    ) -> FsResult<V> {
+        let value: &T = self.get_ref(offset);
+        if !value.checksum_ok() {
+            return Err(FsError::Corrupted);
+        }
+        Ok(f(value))
+    }
+
+    /// Like `modify`, but verify the checksum of `T` first and update it afterwards.
+    pub fn modify_checked<T: Checksummed, V>(
+        &mut self,
+        offset: usize,
+        f: impl FnOnce(&mut T) -> V,
+    ) -> FsResult<V> {
+        if !self.get_ref::<T>(offset).checksum_ok() {
+            return Err(FsError::Corrupted);
+        }
+        let value: &mut T = self.get_mut(offset);
+        let ret = f(value);
+        value.update_checksum();
+        Ok(ret)
+    }
+
+    /// The block stays dirty if the write fails.
+    pub fn sync(&mut self) -> FsResult {
         if self.modified {
+            self.block_device.write_block(self.block_id, &self.cache)?;
             self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache);
         }
+        Ok(())
     }
 }
 
 impl Drop for BlockCache {
+    /// Errors are lost here, caches are synced before being evicted.
     fn drop(&mut self) {
-        self.sync()
+        let _ = self.sync();
     }
 }
 
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// Caches are keyed by the device too, so several filesystems can be mounted.
+type BlockKey = (usize, usize);
+
+fn block_key(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> BlockKey {
+    (Arc::as_ptr(block_device) as *const u8 as usize, block_id)
+}
+
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    queue: VecDeque<(BlockKey, Arc<Mutex<BlockCache>>)>,
 }
 
 impl BlockCacheManager {
@@ -88,31 +128,41 @@ impl BlockCacheManager {
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
-    ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
+    ) -> FsResult<Arc<Mutex<BlockCache>>> {
+        let key = block_key(block_id, &block_device);
+        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == key) {
+            Ok(Arc::clone(&pair.1))
         } else {
             // substitute
             if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
+                // from front to tail, skip blocks which can not be written back
+                let mut victim = Err(None);
+                for (idx, pair) in self.queue.iter().enumerate() {
+                    if Arc::strong_count(&pair.1) == 1 {
+                        match pair.1.lock().sync() {
+                            Ok(()) => {
+                                victim = Ok(idx);
+                                break;
+                            }
+                            Err(err) => victim = Err(Some(err)),
+                        }
+                    }
+                }
+                match victim {
+                    Ok(idx) => {
+                        self.queue.drain(idx..=idx);
+                    }
+                    Err(Some(err)) => return Err(err),
+                    Err(None) => panic!("Run out of BlockCache!"),
                 }
             }
             // load block into mem and push back
             let block_cache = Arc::new(Mutex::new(BlockCache::new(
                 block_id,
                 Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+            )?));
+            self.queue.push_back((key, Arc::clone(&block_cache)));
+            Ok(block_cache)
         }
     }
 }
@@ -122,18 +172,65 @@ lazy_static! {
         Mutex::new(BlockCacheManager::new());
 }
 
+/// Do not call this while holding another block, `block_cache_sync_all` locks
+/// blocks while holding the manager.
 pub fn get_block_cache(
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
-) -> Arc<Mutex<BlockCache>> {
+) -> FsResult<Arc<Mutex<BlockCache>>> {
     BLOCK_CACHE_MANAGER
         .lock()
         .get_block_cache(block_id, block_device)
 }
 
-pub fn block_cache_sync_all() {
+/// Try to sync every block and flush the devices, return the first error.
+pub fn block_cache_sync_all() -> FsResult {
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+    let mut result = Ok(());
+    let mut devices: Vec<Arc<dyn BlockDevice>> = Vec::new();
+    for (key, cache) in manager.queue.iter() {
+        let mut cache = cache.lock();
+        if let Err(err) = cache.sync() {
+            result = result.and(Err(err));
+        }
+        if !devices.iter().any(|device| block_key(0, device).0 == key.0) {
+            devices.push(Arc::clone(&cache.block_device));
+        }
     }
+    drop(manager);
+    for device in devices {
+        result = result.and(device.flush());
+    }
+    result
+}
+
+/// Forget the cached copies of `blocks` without writing them back.
+/// Blocks still in use are kept.
+pub fn block_cache_discard(block_device: &Arc<dyn BlockDevice>, blocks: Range<usize>) {
+    let device = block_key(0, block_device).0;
+    BLOCK_CACHE_MANAGER.lock().queue.retain(|(key, cache)| {
+        if key.0 != device || !blocks.contains(&key.1) || Arc::strong_count(cache) > 1 {
+            return true;
+        }
+        cache.lock().modified = false;
+        false
+    });
+}
+
+/// Write back and forget every cached block of `block_device`, then flush it.
+/// Blocks which fail to sync or are still in use stay cached.
+pub fn block_cache_release(block_device: &Arc<dyn BlockDevice>) -> FsResult {
+    let device = block_key(0, block_device).0;
+    let mut result = Ok(());
+    BLOCK_CACHE_MANAGER.lock().queue.retain(|(key, cache)| {
+        if key.0 != device {
+            return true;
+        }
+        if let Err(err) = cache.lock().sync() {
+            result = result.and(Err(err));
+            return true;
+        }
+        Arc::strong_count(cache) > 1
+    });
+    result.and(block_device.flush())
 }
@@ -1,7 +1,18 @@
+use super::FsResult;
 use core::any::Any;
+use core::ops::Range;
 
 pub trait BlockDevice: Send + Sync + Any {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]);
-    fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Return `FsError::Io` if the device fails.
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult;
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult;
+    /// Make completed writes durable, for devices with a volatile write cache.
+    fn flush(&self) -> FsResult {
+        Ok(())
+    }
+    /// Hint that `blocks` are unused. Their contents are undefined afterwards.
+    fn discard(&self, _blocks: Range<usize>) -> FsResult {
+        Ok(())
+    }
     fn handle_irq(&self);
 }
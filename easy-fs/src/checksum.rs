@@ -0,0 +1,38 @@
+/// CRC-32 (IEEE 802.3), the same one used by zlib and ext4's crc32 fallback.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// CRC-32 of the first `len` bytes of `value`.
+pub fn crc32_of<T>(value: &T, len: usize) -> u32 {
+    assert!(len <= core::mem::size_of::<T>());
+    crc32(unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) })
+}
+
+/// On-disk metadata protected by a checksum stored inside itself.
+pub trait Checksummed {
+    fn checksum_ok(&self) -> bool;
+    fn update_checksum(&mut self);
+}
@@ -1,9 +1,13 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_discard, block_cache_release, block_cache_sync_all, get_block_cache, Bitmap,
+    BlockDevice, Bounds, DiskInode, DiskInodeBytes, DiskInodeType, FsError, FsResult, Inode,
+    SuperBlock, SuperBlockBytes, DISK_INODE_SZ,
 };
+use crate::bitmap::BLOCK_BITS;
 use crate::BLOCK_SZ;
-use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use spin::Mutex;
 
 pub struct EasyFileSystem {
@@ -12,6 +16,11 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    data_area_blocks: u32,
+    /// Set when the image has ro-compat features we do not know.
+    read_only: bool,
+    /// Inodes in use, so that every inode number maps to a unique `Inode`.
+    inode_cache: BTreeMap<u32, Weak<Inode>>,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
@@ -21,15 +30,14 @@ impl EasyFileSystem {
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
-    ) -> Arc<Mutex<Self>> {
+    ) -> FsResult<Arc<Mutex<Self>>> {
         // calculate block size of areas & create bitmaps
         let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
         let inode_num = inode_bitmap.maximum();
-        let inode_area_blocks =
-            ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
+        let inode_area_blocks = ((inode_num * DISK_INODE_SZ + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
         let data_total_blocks = total_blocks - 1 - inode_total_blocks;
-        let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
+        let data_bitmap_blocks = (data_total_blocks + BLOCK_BITS as u32) / (BLOCK_BITS as u32 + 1);
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
             (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
@@ -41,10 +49,13 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
+            read_only: false,
+            inode_cache: BTreeMap::new(),
         };
         // clear all blocks
         for i in 0..total_blocks {
-            get_block_cache(i as usize, Arc::clone(&block_device))
+            get_block_cache(i as usize, Arc::clone(&block_device))?
                 .lock()
                 .modify(0, |data_block: &mut DataBlock| {
                     for byte in data_block.iter_mut() {
@@ -52,69 +63,221 @@ impl EasyFileSystem {
                     }
                 });
         }
+        efs.inode_bitmap.initialize(&block_device)?;
+        efs.data_bitmap.initialize(&block_device)?;
         // initialize SuperBlock
-        get_block_cache(0, Arc::clone(&block_device)).lock().modify(
-            0,
-            |super_block: &mut SuperBlock| {
-                super_block.initialize(
-                    total_blocks,
-                    inode_bitmap_blocks,
-                    inode_area_blocks,
-                    data_bitmap_blocks,
-                    data_area_blocks,
-                );
-            },
+        let super_block = SuperBlock::new(
+            total_blocks,
+            inode_bitmap_blocks,
+            inode_area_blocks,
+            data_bitmap_blocks,
+            data_area_blocks,
         );
+        get_block_cache(0, Arc::clone(&block_device))?
+            .lock()
+            .modify(0, |bytes: &mut SuperBlockBytes| super_block.encode(bytes));
         // write back immediately
         // create a inode for root node "/"
-        assert_eq!(efs.alloc_inode(), 0);
+        assert_eq!(efs.alloc_inode()?, 0);
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
-        get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
+        get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))?
             .lock()
-            .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+            .modify(root_inode_offset, |bytes: &mut DiskInodeBytes| {
+                DiskInode::new(DiskInodeType::Directory, efs.bounds()).encode(bytes);
             });
-        block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        block_cache_sync_all()?;
+        Ok(Arc::new(Mutex::new(efs)))
     }
 
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> FsResult<Arc<Mutex<Self>>> {
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let super_block = get_block_cache(0, Arc::clone(&block_device))?
             .lock()
-            .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
-                let inode_total_blocks =
-                    super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
-                    block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
-                    data_bitmap: Bitmap::new(
-                        (1 + inode_total_blocks) as usize,
-                        super_block.data_bitmap_blocks as usize,
-                    ),
-                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
-                    data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
-            })
-    }
-
-    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
-        // acquire efs lock temporarily
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+            .read(0, SuperBlock::decode)?;
+        let read_only = super_block.check_features()?;
+        let inode_total_blocks = super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
+        let efs = Self {
+            block_device,
+            inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
+            data_bitmap: Bitmap::new(
+                (1 + inode_total_blocks) as usize,
+                super_block.data_bitmap_blocks as usize,
+            ),
+            inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
+            data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+            data_area_blocks: super_block.data_area_blocks,
+            read_only,
+            inode_cache: BTreeMap::new(),
+        };
+        Ok(Arc::new(Mutex::new(efs)))
+    }
+
+    /// Write back everything cached for the filesystem on `block_device`, so
+    /// that the device can be detached.
+    pub fn unmount(block_device: &Arc<dyn BlockDevice>) -> FsResult {
+        block_cache_release(block_device)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Valid targets of block ids and inode numbers found on disk.
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            data_start: self.data_area_start_block,
+            data_end: self.data_area_start_block + self.data_area_blocks,
+            inodes: self.inode_bitmap.maximum() as u32,
+        }
+    }
+
+    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Arc<Inode> {
+        Self::get_inode(efs, 0)
+    }
+
+    /// Get the `Inode` of `inode_id`, reusing the cached one if it is still alive.
+    pub fn get_inode(efs: &Arc<Mutex<Self>>, inode_id: u32) -> Arc<Inode> {
+        let mut fs = efs.lock();
+        if let Some(inode) = fs.inode_cache.get(&inode_id).and_then(Weak::upgrade) {
+            return inode;
+        }
+        // evict inodes which are no longer used
+        fs.inode_cache.retain(|_, inode| inode.strong_count() > 0);
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let inode = Arc::new(Inode::new(
+            inode_id,
+            block_id,
+            block_offset,
+            Arc::clone(efs),
+            Arc::clone(&fs.block_device),
+            fs.bounds(),
+            fs.read_only,
+        ));
+        fs.inode_cache.insert(inode_id, Arc::downgrade(&inode));
+        inode
+    }
+
+    /// Extend the data area to `new_total_blocks` after the device has been enlarged.
+    ///
+    /// If the data bitmap needs more blocks, the first data blocks are moved to the
+    /// new space and all pointers to them are rewritten. File operations are
+    /// blocked meanwhile.
+    pub fn grow(efs: &Arc<Mutex<Self>>, new_total_blocks: u32) -> FsResult {
+        // stop all file operations, back off if an inode is busy to avoid deadlock
+        loop {
+            let mut fs = efs.lock();
+            let inodes: Vec<_> = fs.inode_cache.values().filter_map(Weak::upgrade).collect();
+            let locks: Option<Vec<_>> = inodes.iter().map(|inode| inode.try_lock_write()).collect();
+            if locks.is_some() {
+                let result = fs.grow_locked(new_total_blocks);
+                for inode in inodes.iter() {
+                    inode.set_bounds(fs.bounds());
+                }
+                // dropping an unlinked inode frees it, which takes the efs lock
+                drop(locks);
+                drop(fs);
+                return result;
+            }
+            drop(locks);
+            drop(fs);
+            core::hint::spin_loop();
+        }
+    }
+
+    fn grow_locked(&mut self, new_total_blocks: u32) -> FsResult {
+        if self.read_only {
+            return Err(FsError::ReadOnly);
+        }
+        let block_device = Arc::clone(&self.block_device);
+        let mut super_block = get_block_cache(0, Arc::clone(&block_device))?
+            .lock()
+            .read(0, SuperBlock::decode)?;
+        let old_total_blocks = super_block.total_blocks;
+        let inode_total_blocks = super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
+        if new_total_blocks < old_total_blocks {
+            return Err(FsError::InvalidArgument);
+        }
+        let data_total_blocks = new_total_blocks - 1 - inode_total_blocks;
+        let data_bitmap_blocks = (data_total_blocks + BLOCK_BITS as u32) / (BLOCK_BITS as u32 + 1);
+        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        let old_start = self.data_area_start_block;
+        let new_start = 1 + inode_total_blocks + data_bitmap_blocks;
+        // clear the new space
+        for block_id in old_total_blocks..new_total_blocks {
+            get_block_cache(block_id as usize, Arc::clone(&block_device))?
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| data_block.fill(0));
+        }
+        // move blocks which will be covered by the bitmap into the new space
+        let mut used: Vec<u32> = self
+            .data_bitmap
+            .allocated(&block_device)?
+            .into_iter()
+            .map(|bit| bit as u32 + old_start)
+            .collect();
+        let mut remap = BTreeMap::new();
+        // the new space is always larger than the blocks taken by the bitmap
+        let moved = used.iter_mut().filter(|block_id| **block_id < new_start);
+        for (block_id, free_block) in moved.zip(old_total_blocks..) {
+            let data = get_block_cache(*block_id as usize, Arc::clone(&block_device))?
+                .lock()
+                .read(0, |data_block: &DataBlock| *data_block);
+            get_block_cache(free_block as usize, Arc::clone(&block_device))?
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| *data_block = data);
+            remap.insert(*block_id, free_block);
+            *block_id = free_block;
+        }
+        if !remap.is_empty() {
+            // pointers may target both the old and the new data area meanwhile
+            let bounds = Bounds {
+                data_end: new_total_blocks,
+                ..self.bounds()
+            };
+            for inode_id in self.inode_bitmap.allocated(&block_device)? {
+                let (block_id, block_offset) = self.get_disk_inode_pos(inode_id as u32);
+                let inode_block = get_block_cache(block_id as usize, Arc::clone(&block_device))?;
+                let mut disk_inode = inode_block
+                    .lock()
+                    .read(block_offset, |bytes: &DiskInodeBytes| {
+                        DiskInode::decode(bytes, bounds)
+                    })?;
+                disk_inode.remap_blocks(&remap, &block_device)?;
+                inode_block
+                    .lock()
+                    .modify(block_offset, |bytes: &mut DiskInodeBytes| {
+                        disk_inode.encode(bytes)
+                    });
+            }
+        }
+        // rebuild the data bitmap
+        let data_bitmap = Bitmap::new(
+            (1 + inode_total_blocks) as usize,
+            data_bitmap_blocks as usize,
+        );
+        data_bitmap.initialize(&block_device)?;
+        for block_id in used {
+            data_bitmap.set(&block_device, (block_id - new_start) as usize)?;
+        }
+        super_block.total_blocks = new_total_blocks;
+        super_block.data_bitmap_blocks = data_bitmap_blocks;
+        super_block.data_area_blocks = data_area_blocks;
+        get_block_cache(0, Arc::clone(&block_device))?
+            .lock()
+            .modify(0, |bytes: &mut SuperBlockBytes| super_block.encode(bytes));
+        self.data_bitmap = data_bitmap;
+        self.data_area_start_block = new_start;
+        self.data_area_blocks = data_area_blocks;
+        block_cache_sync_all()?;
+        Ok(())
     }
 
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
-        let inode_size = core::mem::size_of::<DiskInode>();
-        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        let inodes_per_block = (BLOCK_SZ / DISK_INODE_SZ) as u32;
         let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
         (
             block_id,
-            (inode_id % inodes_per_block) as usize * inode_size,
+            (inode_id % inodes_per_block) as usize * DISK_INODE_SZ,
         )
     }
 
@@ -122,26 +285,56 @@ impl EasyFileSystem {
         self.data_area_start_block + data_block_id
     }
 
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    pub fn alloc_inode(&mut self) -> FsResult<u32> {
+        let inode_id = self.inode_bitmap.alloc(&self.block_device)?;
+        Ok(inode_id.ok_or(FsError::NoSpace)? as u32)
     }
 
-    /// Return a block ID not ID in the data area.
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    pub fn dealloc_inode(&mut self, inode_id: u32) -> FsResult {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
     }
 
-    pub fn dealloc_data(&mut self, block_id: u32) {
-        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+    /// Return a block ID not ID in the data area.
+    ///
+    /// The block is zeroed, freed blocks are discarded and may hold anything.
+    pub fn alloc_data(&mut self) -> FsResult<u32> {
+        let data_block_id = self
+            .data_bitmap
+            .alloc(&self.block_device)?
+            .ok_or(FsError::NoSpace)?;
+        // the last bitmap block may cover more bits than the data area
+        if data_block_id >= self.data_area_blocks as usize {
+            self.data_bitmap
+                .dealloc(&self.block_device, data_block_id)?;
+            return Err(FsError::NoSpace);
+        }
+        let block_id = data_block_id as u32 + self.data_area_start_block;
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))?
             .lock()
-            .modify(0, |data_block: &mut DataBlock| {
-                data_block.iter_mut().for_each(|p| {
-                    *p = 0;
-                })
-            });
+            .modify(0, |data_block: &mut DataBlock| data_block.fill(0));
+        Ok(block_id)
+    }
+
+    /// The block is freed even if discarding it fails, that error is still returned.
+    pub fn dealloc_data(&mut self, block_id: u32) -> FsResult {
+        self.bounds().check_block(block_id)?;
+        let block_id = block_id as usize;
+        block_cache_discard(&self.block_device, block_id..block_id + 1);
+        let discarded = self.block_device.discard(block_id..block_id + 1);
         self.data_bitmap.dealloc(
             &self.block_device,
-            (block_id - self.data_area_start_block) as usize,
-        )
+            block_id - self.data_area_start_block as usize,
+        )?;
+        discarded
+    }
+
+    /// Free all of `blocks`, also after one fails. Return the first error.
+    pub fn dealloc_blocks(&mut self, blocks: Vec<u32>) -> FsResult {
+        let mut result = Ok(());
+        for block_id in blocks {
+            result = result.and(self.dealloc_data(block_id));
+        }
+        result
     }
 }
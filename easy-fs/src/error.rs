@@ -0,0 +1,44 @@
+use core::fmt::{Display, Formatter, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// Metadata on disk does not match its checksum.
+    Corrupted,
+    /// No such file, directory or extended attribute.
+    NotFound,
+    /// No room left in the filesystem or in the xattr block.
+    NoSpace,
+    /// Bad name or value, e.g. an empty xattr name.
+    InvalidArgument,
+    /// Not an easy-fs image, or it needs features we do not know.
+    Unsupported,
+    /// Mounted read-only.
+    ReadOnly,
+    /// The block device failed to read or write.
+    Io,
+    /// A directory operation on a file.
+    NotDir,
+    /// A file operation on a directory.
+    IsDir,
+    /// Removing a directory which still has entries.
+    NotEmpty,
+}
+
+pub type FsResult<T = ()> = core::result::Result<T, FsError>;
+
+impl Display for FsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Corrupted => write!(f, "metadata checksum mismatch"),
+            Self::NotFound => write!(f, "not found"),
+            Self::NoSpace => write!(f, "no space left"),
+            Self::InvalidArgument => write!(f, "invalid argument"),
+            Self::Unsupported => write!(f, "unsupported filesystem"),
+            Self::ReadOnly => write!(f, "read-only filesystem"),
+            Self::Io => write!(f, "I/O error"),
+            Self::NotDir => write!(f, "not a directory"),
+            Self::IsDir => write!(f, "is a directory"),
+            Self::NotEmpty => write!(f, "directory not empty"),
+        }
+    }
+}
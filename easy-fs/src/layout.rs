@@ -1,21 +1,80 @@
-use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use super::{get_block_cache, BlockDevice, Checksummed, FsError, FsResult, BLOCK_SZ};
+use crate::bitmap::BLOCK_BITS;
+use crate::checksum::{crc32, crc32_of};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
 const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
+const EFS_VERSION: u32 = 1;
+/// Features known by this implementation, an unknown compat feature is ignored,
+/// an unknown ro-compat feature forces a read-only mount and an unknown
+/// incompat feature refuses the mount.
+const FEATURE_COMPAT_SUPPORTED: u32 = 0;
+const FEATURE_RO_COMPAT_SUPPORTED: u32 = 0;
+const FEATURE_INCOMPAT_SUPPORTED: u32 = 0;
+const INODE_DIRECT_COUNT: usize = 26;
 const NAME_LENGTH_LIMIT: usize = 27;
-const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
+/// The last u32 of an indirect block holds its checksum.
+const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4 - 1;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
 
-#[repr(C)]
+/// Bytes of the superblock at the start of block 0, the last u32 is its checksum.
+pub type SuperBlockBytes = [u8; SUPER_BLOCK_SZ];
+/// Bytes of a disk inode in the inode area, the last u32 is its checksum.
+pub type DiskInodeBytes = [u8; DISK_INODE_SZ];
+/// Bytes of a directory entry in a directory's data.
+pub type DirEntryBytes = [u8; DIRENT_SZ];
+
+const SUPER_BLOCK_SZ: usize = 44;
+pub const DISK_INODE_SZ: usize = 128;
+pub const DIRENT_SZ: usize = 32;
+/// Files are limited by the blocks reachable through indirect2.
+pub const MAX_FILE_SIZE: usize = INDIRECT2_BOUND * BLOCK_SZ;
+
+/// All on-disk integers are little-endian.
+fn get_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+fn put_u32(bytes: &mut [u8], pos: usize, value: u32) {
+    bytes[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Where block ids and inode numbers read from disk may point to.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub data_start: u32,
+    pub data_end: u32,
+    pub inodes: u32,
+}
+
+impl Bounds {
+    pub fn check_block(&self, block_id: u32) -> FsResult<u32> {
+        if block_id < self.data_start || block_id >= self.data_end {
+            return Err(FsError::Corrupted);
+        }
+        Ok(block_id)
+    }
+    pub fn check_inode(&self, inode_id: u32) -> FsResult<u32> {
+        if inode_id >= self.inodes {
+            return Err(FsError::Corrupted);
+        }
+        Ok(inode_id)
+    }
+}
+
 pub struct SuperBlock {
     magic: u32,
+    version: u32,
+    feature_compat: u32,
+    feature_ro_compat: u32,
+    feature_incompat: u32,
     pub total_blocks: u32,
     pub inode_bitmap_blocks: u32,
     pub inode_area_blocks: u32,
@@ -26,6 +85,10 @@ pub struct SuperBlock {
 impl Debug for SuperBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_struct("SuperBlock")
+            .field("version", &self.version)
+            .field("feature_compat", &self.feature_compat)
+            .field("feature_ro_compat", &self.feature_ro_compat)
+            .field("feature_incompat", &self.feature_incompat)
             .field("total_blocks", &self.total_blocks)
             .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
             .field("inode_area_blocks", &self.inode_area_blocks)
@@ -36,16 +99,19 @@ impl Debug for SuperBlock {
 }
 
 impl SuperBlock {
-    pub fn initialize(
-        &mut self,
+    pub fn new(
         total_blocks: u32,
         inode_bitmap_blocks: u32,
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
         data_area_blocks: u32,
-    ) {
-        *self = Self {
+    ) -> Self {
+        Self {
             magic: EFS_MAGIC,
+            version: EFS_VERSION,
+            feature_compat: FEATURE_COMPAT_SUPPORTED,
+            feature_ro_compat: FEATURE_RO_COMPAT_SUPPORTED,
+            feature_incompat: FEATURE_INCOMPAT_SUPPORTED,
             total_blocks,
             inode_bitmap_blocks,
             inode_area_blocks,
@@ -54,36 +120,188 @@ impl SuperBlock {
         }
     }
     pub fn is_valid(&self) -> bool {
-        self.magic == EFS_MAGIC
+        self.magic == EFS_MAGIC && self.version <= EFS_VERSION
+    }
+    /// Return whether the image can only be mounted read-only.
+    pub fn check_features(&self) -> FsResult<bool> {
+        if !self.is_valid() || self.feature_incompat & !FEATURE_INCOMPAT_SUPPORTED != 0 {
+            return Err(FsError::Unsupported);
+        }
+        Ok(self.feature_ro_compat & !FEATURE_RO_COMPAT_SUPPORTED != 0)
+    }
+    /// The areas must fill the image and the bitmaps must cover their areas.
+    fn check_layout(&self) -> FsResult {
+        let areas = [
+            self.inode_bitmap_blocks,
+            self.inode_area_blocks,
+            self.data_bitmap_blocks,
+            self.data_area_blocks,
+        ];
+        let used = areas.iter().map(|blocks| *blocks as u64).sum::<u64>() + 1;
+        let inodes = self.inode_bitmap_blocks as u64 * BLOCK_BITS as u64;
+        if used != self.total_blocks as u64
+            || inodes * DISK_INODE_SZ as u64 > self.inode_area_blocks as u64 * BLOCK_SZ as u64
+            || (self.data_area_blocks as u64) > self.data_bitmap_blocks as u64 * BLOCK_BITS as u64
+        {
+            return Err(FsError::Corrupted);
+        }
+        Ok(())
+    }
+    pub fn decode(bytes: &SuperBlockBytes) -> FsResult<Self> {
+        let super_block = Self {
+            magic: get_u32(bytes, 0),
+            version: get_u32(bytes, 4),
+            feature_compat: get_u32(bytes, 8),
+            feature_ro_compat: get_u32(bytes, 12),
+            feature_incompat: get_u32(bytes, 16),
+            total_blocks: get_u32(bytes, 20),
+            inode_bitmap_blocks: get_u32(bytes, 24),
+            inode_area_blocks: get_u32(bytes, 28),
+            data_bitmap_blocks: get_u32(bytes, 32),
+            data_area_blocks: get_u32(bytes, 36),
+        };
+        if !super_block.is_valid() {
+            return Err(FsError::Unsupported);
+        }
+        if get_u32(bytes, SUPER_BLOCK_SZ - 4) != crc32(&bytes[..SUPER_BLOCK_SZ - 4]) {
+            return Err(FsError::Corrupted);
+        }
+        super_block.check_layout()?;
+        Ok(super_block)
+    }
+    pub fn encode(&self, bytes: &mut SuperBlockBytes) {
+        let fields = [
+            self.magic,
+            self.version,
+            self.feature_compat,
+            self.feature_ro_compat,
+            self.feature_incompat,
+            self.total_blocks,
+            self.inode_bitmap_blocks,
+            self.inode_area_blocks,
+            self.data_bitmap_blocks,
+            self.data_area_blocks,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            put_u32(bytes, i * 4, *field);
+        }
+        let checksum = crc32(&bytes[..SUPER_BLOCK_SZ - 4]);
+        put_u32(bytes, SUPER_BLOCK_SZ - 4, checksum);
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
 }
 
-type IndirectBlock = [u32; BLOCK_SZ / 4];
+/// Entries of an indirect block are little-endian, the last u32 holds its checksum.
+type IndirectBlock = [u32; INODE_INDIRECT1_COUNT + 1];
 type DataBlock = [u8; BLOCK_SZ];
 
-#[repr(C)]
+impl Checksummed for IndirectBlock {
+    fn checksum_ok(&self) -> bool {
+        u32::from_le(self[INODE_INDIRECT1_COUNT]) == crc32_of(self, INODE_INDIRECT1_COUNT * 4)
+    }
+    fn update_checksum(&mut self) {
+        self[INODE_INDIRECT1_COUNT] = crc32_of(self, INODE_INDIRECT1_COUNT * 4).to_le();
+    }
+}
+
+/// Initialize a newly allocated indirect block.
+fn init_indirect_block(block_id: u32, block_device: &Arc<dyn BlockDevice>) -> FsResult {
+    get_block_cache(block_id as usize, Arc::clone(block_device))?
+        .lock()
+        .modify(0, |indirect_block: &mut IndirectBlock| {
+            indirect_block.iter_mut().for_each(|v| *v = 0);
+            indirect_block.update_checksum();
+        });
+    Ok(())
+}
+
+/// In-memory copy of an inode, see `decode` and `encode` for the disk format.
+#[derive(Clone)]
 pub struct DiskInode {
     pub size: u32,
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Block of extended attributes, 0 if there is none.
+    pub xattr: u32,
     type_: DiskInodeType,
+    /// Not stored, used to check block ids read from indirect blocks.
+    bounds: Bounds,
 }
 
 impl DiskInode {
     /// indirect1 and indirect2 block are allocated only when they are needed.
-    pub fn initialize(&mut self, type_: DiskInodeType) {
-        self.size = 0;
-        self.direct.iter_mut().for_each(|v| *v = 0);
-        self.indirect1 = 0;
-        self.indirect2 = 0;
-        self.type_ = type_;
+    pub fn new(type_: DiskInodeType, bounds: Bounds) -> Self {
+        Self {
+            size: 0,
+            direct: [0; INODE_DIRECT_COUNT],
+            indirect1: 0,
+            indirect2: 0,
+            xattr: 0,
+            type_,
+            bounds,
+        }
+    }
+    /// Check the checksum, the type and that all used blocks are in `bounds`.
+    pub fn decode(bytes: &DiskInodeBytes, bounds: Bounds) -> FsResult<Self> {
+        if get_u32(bytes, DISK_INODE_SZ - 4) != crc32(&bytes[..DISK_INODE_SZ - 4]) {
+            return Err(FsError::Corrupted);
+        }
+        let type_ = match get_u32(bytes, 120) {
+            0 => DiskInodeType::File,
+            1 => DiskInodeType::Directory,
+            _ => return Err(FsError::Corrupted),
+        };
+        let mut direct = [0u32; INODE_DIRECT_COUNT];
+        for (i, block_id) in direct.iter_mut().enumerate() {
+            *block_id = get_u32(bytes, 4 + i * 4);
+        }
+        let disk_inode = Self {
+            size: get_u32(bytes, 0),
+            direct,
+            indirect1: get_u32(bytes, 108),
+            indirect2: get_u32(bytes, 112),
+            xattr: get_u32(bytes, 116),
+            type_,
+            bounds,
+        };
+        if disk_inode.size as usize > MAX_FILE_SIZE {
+            return Err(FsError::Corrupted);
+        }
+        let data_blocks = disk_inode.data_blocks() as usize;
+        for block_id in disk_inode.direct.iter().take(data_blocks) {
+            bounds.check_block(*block_id)?;
+        }
+        if data_blocks > INODE_DIRECT_COUNT {
+            bounds.check_block(disk_inode.indirect1)?;
+        }
+        if data_blocks > INDIRECT1_BOUND {
+            bounds.check_block(disk_inode.indirect2)?;
+        }
+        if disk_inode.xattr != 0 {
+            bounds.check_block(disk_inode.xattr)?;
+        }
+        Ok(disk_inode)
+    }
+    pub fn encode(&self, bytes: &mut DiskInodeBytes) {
+        put_u32(bytes, 0, self.size);
+        for (i, block_id) in self.direct.iter().enumerate() {
+            put_u32(bytes, 4 + i * 4, *block_id);
+        }
+        put_u32(bytes, 108, self.indirect1);
+        put_u32(bytes, 112, self.indirect2);
+        put_u32(bytes, 116, self.xattr);
+        put_u32(bytes, 120, self.type_ as u32);
+        let checksum = crc32(&bytes[..DISK_INODE_SZ - 4]);
+        put_u32(bytes, DISK_INODE_SZ - 4, checksum);
+    }
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
     }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
@@ -120,28 +338,35 @@ impl DiskInode {
         assert!(new_size >= self.size);
         Self::total_blocks(new_size) - Self::total_blocks(self.size)
     }
-    pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
+    pub fn get_block_id(
+        &self,
+        inner_id: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> FsResult<u32> {
         let inner_id = inner_id as usize;
         if inner_id < INODE_DIRECT_COUNT {
-            self.direct[inner_id]
+            self.bounds.check_block(self.direct[inner_id])
         } else if inner_id < INDIRECT1_BOUND {
-            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))?
                 .lock()
-                .read(0, |indirect_block: &IndirectBlock| {
-                    indirect_block[inner_id - INODE_DIRECT_COUNT]
-                })
+                .read_checked(0, |indirect_block: &IndirectBlock| {
+                    self.bounds
+                        .check_block(u32::from_le(indirect_block[inner_id - INODE_DIRECT_COUNT]))
+                })?
         } else {
             let last = inner_id - INDIRECT1_BOUND;
-            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))?
                 .lock()
-                .read(0, |indirect2: &IndirectBlock| {
-                    indirect2[last / INODE_INDIRECT1_COUNT]
-                });
-            get_block_cache(indirect1 as usize, Arc::clone(block_device))
+                .read_checked(0, |indirect2: &IndirectBlock| {
+                    self.bounds
+                        .check_block(u32::from_le(indirect2[last / INODE_INDIRECT1_COUNT]))
+                })??;
+            get_block_cache(indirect1 as usize, Arc::clone(block_device))?
                 .lock()
-                .read(0, |indirect1: &IndirectBlock| {
-                    indirect1[last % INODE_INDIRECT1_COUNT]
-                })
+                .read_checked(0, |indirect1: &IndirectBlock| {
+                    self.bounds
+                        .check_block(u32::from_le(indirect1[last % INODE_INDIRECT1_COUNT]))
+                })?
         }
     }
     pub fn increase_size(
@@ -149,7 +374,7 @@ impl DiskInode {
         new_size: u32,
         new_blocks: Vec<u32>,
         block_device: &Arc<dyn BlockDevice>,
-    ) {
+    ) -> FsResult {
         let mut current_blocks = self.data_blocks();
         self.size = new_size;
         let mut total_blocks = self.data_blocks();
@@ -163,50 +388,54 @@ impl DiskInode {
         if total_blocks > INODE_DIRECT_COUNT as u32 {
             if current_blocks == INODE_DIRECT_COUNT as u32 {
                 self.indirect1 = new_blocks.next().unwrap();
+                init_indirect_block(self.indirect1, block_device)?;
             }
             current_blocks -= INODE_DIRECT_COUNT as u32;
             total_blocks -= INODE_DIRECT_COUNT as u32;
         } else {
-            return;
+            return Ok(());
         }
         // fill indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))?
             .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
+            .modify_checked(0, |indirect1: &mut IndirectBlock| {
                 while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
+                    indirect1[current_blocks as usize] = new_blocks.next().unwrap().to_le();
                     current_blocks += 1;
                 }
-            });
+            })?;
         // alloc indirect2
         if total_blocks > INODE_INDIRECT1_COUNT as u32 {
             if current_blocks == INODE_INDIRECT1_COUNT as u32 {
                 self.indirect2 = new_blocks.next().unwrap();
+                init_indirect_block(self.indirect2, block_device)?;
             }
             current_blocks -= INODE_INDIRECT1_COUNT as u32;
             total_blocks -= INODE_INDIRECT1_COUNT as u32;
         } else {
-            return;
+            return Ok(());
         }
         // fill indirect2 from (a0, b0) -> (a1, b1)
         let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
         let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
         let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
         let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-        // alloc low-level indirect1
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        // alloc low-level indirect1, no other block is locked while indirect2 is
+        let mut fresh: Vec<u32> = Vec::new();
+        let mut fills: Vec<(u32, usize, u32)> = Vec::new();
+        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))?
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
+            .modify_checked(0, |indirect2: &mut IndirectBlock| {
                 while (a0 < a1) || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // fill current
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
+                    let indirect1_id = if b0 == 0 {
+                        let block_id = new_blocks.next().unwrap();
+                        indirect2[a0] = block_id.to_le();
+                        fresh.push(block_id);
+                        block_id
+                    } else {
+                        self.bounds.check_block(u32::from_le(indirect2[a0]))?
+                    };
+                    fills.push((indirect1_id, b0, new_blocks.next().unwrap()));
                     // move to next
                     b0 += 1;
                     if b0 == INODE_INDIRECT1_COUNT {
@@ -214,13 +443,136 @@ impl DiskInode {
                         a0 += 1;
                     }
                 }
-            });
+                Ok(())
+            })??;
+        for block_id in fresh {
+            init_indirect_block(block_id, block_device)?;
+        }
+        // fill current
+        for (indirect1_id, b0, block_id) in fills {
+            get_block_cache(indirect1_id as usize, Arc::clone(block_device))?
+                .lock()
+                .modify_checked(0, |indirect1: &mut IndirectBlock| {
+                    indirect1[b0] = block_id.to_le();
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Replace the block ids found in `remap`, including those in indirect blocks.
+    ///
+    /// Indirect blocks must have been copied to their new place before.
+    pub fn remap_blocks(
+        &mut self,
+        remap: &BTreeMap<u32, u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> FsResult {
+        let map = |block_id: &mut u32| {
+            if let Some(new_block_id) = remap.get(block_id) {
+                *block_id = *new_block_id;
+            }
+        };
+        let map_le = |entry: &mut u32| {
+            let mut block_id = u32::from_le(*entry);
+            map(&mut block_id);
+            *entry = block_id.to_le();
+        };
+        let data_blocks = self.data_blocks() as usize;
+        self.direct
+            .iter_mut()
+            .take(data_blocks.min(INODE_DIRECT_COUNT))
+            .for_each(map);
+        if self.xattr != 0 {
+            map(&mut self.xattr);
+        }
+        if data_blocks <= INODE_DIRECT_COUNT {
+            return Ok(());
+        }
+        map(&mut self.indirect1);
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))?
+            .lock()
+            .modify_checked(0, |indirect1: &mut IndirectBlock| {
+                indirect1
+                    .iter_mut()
+                    .take((data_blocks - INODE_DIRECT_COUNT).min(INODE_INDIRECT1_COUNT))
+                    .for_each(map_le);
+            })?;
+        if data_blocks <= INDIRECT1_BOUND {
+            return Ok(());
+        }
+        map(&mut self.indirect2);
+        let last = data_blocks - INDIRECT1_BOUND;
+        let a1 = (last + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+        let indirect1_ids = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))?
+            .lock()
+            .modify_checked(0, |indirect2: &mut IndirectBlock| {
+                let mut indirect1_ids = Vec::new();
+                for entry in indirect2.iter_mut().take(a1) {
+                    map_le(entry);
+                    indirect1_ids.push(self.bounds.check_block(u32::from_le(*entry))?);
+                }
+                Ok(indirect1_ids)
+            })??;
+        for (a, indirect1_id) in indirect1_ids.into_iter().enumerate() {
+            get_block_cache(indirect1_id as usize, Arc::clone(block_device))?
+                .lock()
+                .modify_checked(0, |indirect1: &mut IndirectBlock| {
+                    indirect1
+                        .iter_mut()
+                        .take((last - a * INODE_INDIRECT1_COUNT).min(INODE_INDIRECT1_COUNT))
+                        .for_each(map_le);
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Shrink to `new_size` and return the data and indirect blocks that
+    /// should be deallocated.
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> FsResult<Vec<u32>> {
+        assert!(new_size <= self.size);
+        let mut v: Vec<u32> = Vec::new();
+        let new_blocks = Self::_data_blocks(new_size);
+        let mut data_blocks = self.data_blocks();
+        // from the last block on, each step drops the indirect blocks it emptied
+        while data_blocks > new_blocks {
+            data_blocks -= 1;
+            v.push(self.get_block_id(data_blocks, block_device)?);
+            let inner_id = data_blocks as usize;
+            if inner_id < INODE_DIRECT_COUNT {
+                self.direct[inner_id] = 0;
+            } else if inner_id == INODE_DIRECT_COUNT {
+                v.push(self.indirect1);
+                self.indirect1 = 0;
+            } else if inner_id >= INDIRECT1_BOUND {
+                let last = inner_id - INDIRECT1_BOUND;
+                let (a, b) = (last / INODE_INDIRECT1_COUNT, last % INODE_INDIRECT1_COUNT);
+                if b == 0 {
+                    let indirect1 =
+                        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))?
+                            .lock()
+                            .read_checked(0, |indirect2: &IndirectBlock| {
+                                self.bounds.check_block(u32::from_le(indirect2[a]))
+                            })??;
+                    v.push(indirect1);
+                }
+                if last == 0 {
+                    v.push(self.indirect2);
+                    self.indirect2 = 0;
+                }
+            }
+        }
+        self.size = new_size;
+        Ok(v)
     }
 
     /// Clear size to zero and return blocks that should be deallocated.
     ///
-    /// We will clear the block contents to zero later.
-    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+    /// The caller discards the freed blocks.
+    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> FsResult<Vec<u32>> {
         let mut v: Vec<u32> = Vec::new();
         let mut data_blocks = self.data_blocks() as usize;
         self.size = 0;
@@ -237,70 +589,71 @@ impl DiskInode {
             data_blocks -= INODE_DIRECT_COUNT;
             current_blocks = 0;
         } else {
-            return v;
+            return Ok(v);
         }
         // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))?
             .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
+            .read_checked(0, |indirect1: &IndirectBlock| {
                 while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
+                    v.push(
+                        self.bounds
+                            .check_block(u32::from_le(indirect1[current_blocks]))?,
+                    );
                     current_blocks += 1;
                 }
-            });
+                Ok(())
+            })??;
         self.indirect1 = 0;
         // indirect2 block
         if data_blocks > INODE_INDIRECT1_COUNT {
             v.push(self.indirect2);
             data_blocks -= INODE_INDIRECT1_COUNT;
         } else {
-            return v;
+            return Ok(v);
         }
         // indirect2
         assert!(data_blocks <= INODE_INDIRECT2_COUNT);
         let a1 = data_blocks / INODE_INDIRECT1_COUNT;
         let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        // (indirect1 block, entries used), the last one may be partly used
+        let indirect1_blocks = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))?
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
+            .read_checked(0, |indirect2: &IndirectBlock| {
+                let mut indirect1_blocks = Vec::new();
+                for entry in indirect2.iter().take(a1) {
+                    indirect1_blocks.push((*entry, INODE_INDIRECT1_COUNT));
                 }
-                // last indirect1 block
                 if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
+                    indirect1_blocks.push((indirect2[a1], b1));
                 }
-            });
+                indirect1_blocks
+            })?;
+        for (entry, count) in indirect1_blocks {
+            let indirect1_id = self.bounds.check_block(u32::from_le(entry))?;
+            v.push(indirect1_id);
+            get_block_cache(indirect1_id as usize, Arc::clone(block_device))?
+                .lock()
+                .read_checked(0, |indirect1: &IndirectBlock| {
+                    for entry in indirect1.iter().take(count) {
+                        v.push(self.bounds.check_block(u32::from_le(*entry))?);
+                    }
+                    Ok(())
+                })??;
+        }
         self.indirect2 = 0;
-        v
+        Ok(v)
     }
     pub fn read_at(
         &self,
         offset: usize,
         buf: &mut [u8],
         block_device: &Arc<dyn BlockDevice>,
-    ) -> usize {
+    ) -> FsResult<usize> {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         if start >= end {
-            return 0;
+            return Ok(0);
         }
         let mut start_block = start / BLOCK_SZ;
         let mut read_size = 0usize;
@@ -312,9 +665,9 @@ impl DiskInode {
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
             get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
+                self.get_block_id(start_block as u32, block_device)? as usize,
                 Arc::clone(block_device),
-            )
+            )?
             .lock()
             .read(0, |data_block: &DataBlock| {
                 let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
@@ -328,7 +681,7 @@ impl DiskInode {
             start_block += 1;
             start = end_current_block;
         }
-        read_size
+        Ok(read_size)
     }
     /// File size must be adjusted before.
     pub fn write_at(
@@ -336,7 +689,7 @@ impl DiskInode {
         offset: usize,
         buf: &[u8],
         block_device: &Arc<dyn BlockDevice>,
-    ) -> usize {
+    ) -> FsResult<usize> {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         assert!(start <= end);
@@ -349,9 +702,9 @@ impl DiskInode {
             // write and update write size
             let block_write_size = end_current_block - start;
             get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
+                self.get_block_id(start_block as u32, block_device)? as usize,
                 Arc::clone(block_device),
-            )
+            )?
             .lock()
             .modify(0, |data_block: &mut DataBlock| {
                 let src = &buf[write_size..write_size + block_write_size];
@@ -366,25 +719,18 @@ impl DiskInode {
             start_block += 1;
             start = end_current_block;
         }
-        write_size
+        Ok(write_size)
     }
 }
 
-#[repr(C)]
+/// A NUL-terminated name followed by a little-endian inode number.
 pub struct DirEntry {
     name: [u8; NAME_LENGTH_LIMIT + 1],
     inode_number: u32,
 }
 
-pub const DIRENT_SZ: usize = 32;
-
 impl DirEntry {
-    pub fn empty() -> Self {
-        Self {
-            name: [0u8; NAME_LENGTH_LIMIT + 1],
-            inode_number: 0,
-        }
-    }
+    /// `name` must be valid, see `is_valid_name`.
     pub fn new(name: &str, inode_number: u32) -> Self {
         let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
         bytes[..name.len()].copy_from_slice(name.as_bytes());
@@ -393,17 +739,94 @@ impl DirEntry {
             inode_number,
         }
     }
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.len() <= NAME_LENGTH_LIMIT && !name.contains('\0')
     }
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    pub fn decode(bytes: &DirEntryBytes, bounds: &Bounds) -> FsResult<Self> {
+        let mut name = [0u8; NAME_LENGTH_LIMIT + 1];
+        name.copy_from_slice(&bytes[..NAME_LENGTH_LIMIT + 1]);
+        let len = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(FsError::Corrupted)?;
+        core::str::from_utf8(&name[..len]).map_err(|_| FsError::Corrupted)?;
+        Ok(Self {
+            name,
+            inode_number: bounds.check_inode(get_u32(bytes, NAME_LENGTH_LIMIT + 1))?,
+        })
+    }
+    pub fn encode(&self) -> DirEntryBytes {
+        let mut bytes = [0u8; DIRENT_SZ];
+        bytes[..NAME_LENGTH_LIMIT + 1].copy_from_slice(&self.name);
+        put_u32(&mut bytes, NAME_LENGTH_LIMIT + 1, self.inode_number);
+        bytes
     }
     pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
+        let len = self.name.iter().position(|byte| *byte == 0).unwrap();
         core::str::from_utf8(&self.name[..len]).unwrap()
     }
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
 }
+
+/// Extended attributes of an inode, packed as `name_len: u8, value_len: u16,
+/// name, value` records and ended by a zero `name_len`.
+/// The last 4 bytes hold the checksum.
+pub type XattrBlock = [u8; BLOCK_SZ];
+
+const XATTR_CAPACITY: usize = BLOCK_SZ - 4;
+pub const XATTR_NAME_LIMIT: usize = 255;
+
+impl Checksummed for XattrBlock {
+    fn checksum_ok(&self) -> bool {
+        self[XATTR_CAPACITY..] == crc32_of(self, XATTR_CAPACITY).to_le_bytes()
+    }
+    fn update_checksum(&mut self) {
+        let checksum = crc32_of(self, XATTR_CAPACITY);
+        self[XATTR_CAPACITY..].copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+pub fn decode_xattrs(block: &XattrBlock) -> FsResult<Vec<(String, Vec<u8>)>> {
+    let mut xattrs = Vec::new();
+    let mut pos = 0;
+    while pos < XATTR_CAPACITY && block[pos] != 0 {
+        if pos + 3 > XATTR_CAPACITY {
+            return Err(FsError::Corrupted);
+        }
+        let name_len = block[pos] as usize;
+        let value_len = u16::from_le_bytes([block[pos + 1], block[pos + 2]]) as usize;
+        let name_start = pos + 3;
+        let value_start = name_start + name_len;
+        pos = value_start + value_len;
+        if pos > XATTR_CAPACITY {
+            return Err(FsError::Corrupted);
+        }
+        let name = core::str::from_utf8(&block[name_start..value_start])
+            .map_err(|_| FsError::Corrupted)?;
+        xattrs.push((String::from(name), block[value_start..pos].to_vec()));
+    }
+    Ok(xattrs)
+}
+
+/// Return `FsError::NoSpace` if they do not fit in one block.
+pub fn encode_xattrs(xattrs: &[(String, Vec<u8>)]) -> FsResult<XattrBlock> {
+    let mut block = [0u8; BLOCK_SZ];
+    let mut pos = 0;
+    for (name, value) in xattrs {
+        let name_start = pos + 3;
+        let value_start = name_start + name.len();
+        let end = value_start + value.len();
+        if end > XATTR_CAPACITY {
+            return Err(FsError::NoSpace);
+        }
+        block[pos] = name.len() as u8;
+        block[pos + 1..name_start].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        block[name_start..value_start].copy_from_slice(name.as_bytes());
+        block[value_start..end].copy_from_slice(value);
+        pos = end;
+    }
+    block.update_checksum();
+    Ok(block)
+}
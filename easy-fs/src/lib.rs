@@ -5,14 +5,21 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod checksum;
 mod efs;
+mod error;
 mod layout;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod vfs;
 
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+use block_cache::{block_cache_discard, block_cache_sync_all};
+pub use block_cache::{block_cache_release, get_block_cache, BlockCache};
 pub use block_dev::BlockDevice;
+use checksum::Checksummed;
 pub use efs::EasyFileSystem;
+pub use error::{FsError, FsResult};
 use layout::*;
-pub use vfs::Inode;
+pub use vfs::{Inode, XattrMode};
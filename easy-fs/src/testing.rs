@@ -0,0 +1,145 @@
+//! Block devices for testing easy-fs on the host.
+
+use super::block_cache::block_cache_discard;
+use super::{BlockDevice, FsError, FsResult, BLOCK_SZ};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// A disk kept in memory.
+pub struct RamDisk {
+    blocks: Mutex<Vec<[u8; BLOCK_SZ]>>,
+}
+
+impl RamDisk {
+    pub fn new(total_blocks: usize) -> Self {
+        Self {
+            blocks: Mutex::new(vec![[0u8; BLOCK_SZ]; total_blocks]),
+        }
+    }
+
+    /// Copy the current contents, like unplugging the disk after a crash.
+    /// Blocks still in the block cache are not included.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            blocks: Mutex::new(self.blocks.lock().clone()),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult {
+        buf.copy_from_slice(self.blocks.lock().get(block_id).ok_or(FsError::Io)?);
+        Ok(())
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult {
+        let mut blocks = self.blocks.lock();
+        blocks
+            .get_mut(block_id)
+            .ok_or(FsError::Io)?
+            .copy_from_slice(buf);
+        Ok(())
+    }
+    /// Discarded blocks read back as zeroes.
+    fn discard(&self, blocks: Range<usize>) -> FsResult {
+        let mut disk = self.blocks.lock();
+        disk.get_mut(blocks)
+            .ok_or(FsError::Io)?
+            .iter_mut()
+            .for_each(|block| block.fill(0));
+        Ok(())
+    }
+    fn handle_irq(&self) {}
+}
+
+/// Lose the blocks of `block_device` which are still in the block cache, like
+/// a power failure. The filesystem on it must not be used any more.
+pub fn power_off(block_device: &Arc<dyn BlockDevice>) {
+    block_cache_discard(block_device, 0..usize::MAX);
+}
+
+/// What happens to writes once a `FaultyDevice` has failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Return `FsError::Io` for the failing write and all later ones.
+    Fail,
+    /// Silently discard the failing write and all later ones.
+    Drop,
+    /// Write only the first half of the failing block, discard later writes.
+    Tear,
+    /// Keep writing, but return `FsError::Io` for all later discards.
+    Discard,
+}
+
+/// Pass the first `good_writes` writes to `inner`, then inject `fault`.
+/// Reads are never affected.
+pub struct FaultyDevice {
+    inner: Arc<dyn BlockDevice>,
+    good_writes: usize,
+    fault: Fault,
+    writes: AtomicUsize,
+}
+
+impl FaultyDevice {
+    pub fn new(inner: Arc<dyn BlockDevice>, good_writes: usize, fault: Fault) -> Self {
+        Self {
+            inner,
+            good_writes,
+            fault,
+            writes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of writes issued so far, including failed ones.
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    /// Whether some write has been lost.
+    pub fn has_failed(&self) -> bool {
+        self.writes() > self.good_writes
+    }
+}
+
+impl BlockDevice for FaultyDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult {
+        self.inner.read_block(block_id, buf)
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult {
+        let n = self.writes.fetch_add(1, Ordering::SeqCst);
+        if n < self.good_writes {
+            return self.inner.write_block(block_id, buf);
+        }
+        match self.fault {
+            Fault::Fail => Err(FsError::Io),
+            Fault::Discard => self.inner.write_block(block_id, buf),
+            Fault::Tear if n == self.good_writes => {
+                let mut block = [0u8; BLOCK_SZ];
+                self.inner.read_block(block_id, &mut block)?;
+                block[..BLOCK_SZ / 2].copy_from_slice(&buf[..BLOCK_SZ / 2]);
+                self.inner.write_block(block_id, &block)
+            }
+            Fault::Drop | Fault::Tear => Ok(()),
+        }
+    }
+    fn flush(&self) -> FsResult {
+        if self.has_failed() && self.fault == Fault::Fail {
+            return Err(FsError::Io);
+        }
+        self.inner.flush()
+    }
+    /// Lost like writes once the device has failed.
+    fn discard(&self, blocks: Range<usize>) -> FsResult {
+        if !self.has_failed() {
+            return self.inner.discard(blocks);
+        }
+        match self.fault {
+            Fault::Fail | Fault::Discard => Err(FsError::Io),
+            Fault::Drop | Fault::Tear => Ok(()),
+        }
+    }
+    fn handle_irq(&self) {}
+}
@@ -1,186 +1,492 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    block_cache_sync_all, decode_xattrs, encode_xattrs, get_block_cache, BlockDevice, Bounds,
+    DirEntry, DiskInode, DiskInodeBytes, DiskInodeType, EasyFileSystem, FsError, FsResult,
+    XattrBlock, DIRENT_SZ, MAX_FILE_SIZE, XATTR_NAME_LIMIT,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use spin::{Mutex, MutexGuard};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, RwLock, RwLockWriteGuard};
+
+/// When `set_xattr` may store the attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrMode {
+    /// Add it or replace its value.
+    Set,
+    /// Only if it does not exist yet.
+    Create,
+    /// Only if it exists already.
+    Replace,
+}
 
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
+    /// Copied from the efs, which only changes them while holding `lock`.
+    bounds: Mutex<Bounds>,
+    read_only: bool,
+    /// Protects data and size of this inode. The efs lock is only taken
+    /// while allocating or deallocating blocks.
+    lock: RwLock<()>,
+    /// No entry refers to it any more, it is freed when dropped.
+    unlinked: AtomicBool,
 }
 
 impl Inode {
     /// We should not acquire efs lock here.
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
+        bounds: Bounds,
+        read_only: bool,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
+            bounds: Mutex::new(bounds),
+            read_only,
+            lock: RwLock::new(()),
+            unlinked: AtomicBool::new(false),
+        }
+    }
+
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
+    /// How many inodes of the filesystem are alive, this one included.
+    /// Other handles on the filesystem count too.
+    pub fn live_inodes(&self) -> usize {
+        Arc::strong_count(&self.fs)
+    }
+
+    fn check_writable(&self) -> FsResult {
+        if self.read_only {
+            return Err(FsError::ReadOnly);
         }
+        Ok(())
     }
 
-    fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
-        get_block_cache(self.block_id, Arc::clone(&self.block_device))
+    /// Used to stop all file operations while the efs lock is held.
+    pub(crate) fn try_lock_write(&self) -> Option<RwLockWriteGuard<'_, ()>> {
+        self.lock.try_write()
+    }
+
+    /// The caller should hold the write lock of this inode.
+    pub(crate) fn set_bounds(&self, bounds: Bounds) {
+        *self.bounds.lock() = bounds;
+    }
+
+    fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> FsResult<V> {
+        let bounds = *self.bounds.lock();
+        let disk_inode = get_block_cache(self.block_id, Arc::clone(&self.block_device))?
             .lock()
-            .read(self.block_offset, f)
+            .read(self.block_offset, |bytes: &DiskInodeBytes| {
+                DiskInode::decode(bytes, bounds)
+            })?;
+        Ok(f(&disk_inode))
     }
 
-    fn modify_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
-        get_block_cache(self.block_id, Arc::clone(&self.block_device))
+    /// Writers are serialized by the inode lock, so the decoded copy stays current.
+    fn modify_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> FsResult<V> {
+        // keep the block cached, storing the result after `f` must not fail
+        let inode_block = get_block_cache(self.block_id, Arc::clone(&self.block_device))?;
+        let mut disk_inode = self.read_disk_inode(DiskInode::clone)?;
+        let ret = f(&mut disk_inode);
+        inode_block
             .lock()
-            .modify(self.block_offset, f)
+            .modify(self.block_offset, |bytes: &mut DiskInodeBytes| {
+                disk_inode.encode(bytes)
+            });
+        Ok(ret)
+    }
+
+    fn read_dirent(&self, disk_inode: &DiskInode, i: usize) -> FsResult<DirEntry> {
+        let mut bytes = [0u8; DIRENT_SZ];
+        assert_eq!(
+            disk_inode.read_at(DIRENT_SZ * i, &mut bytes, &self.block_device)?,
+            DIRENT_SZ,
+        );
+        DirEntry::decode(&bytes, &disk_inode.bounds())
     }
 
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        // assert it is a directory
-        assert!(disk_inode.is_dir());
+    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> FsResult<Option<u32>> {
+        if !disk_inode.is_dir() {
+            return Err(FsError::NotDir);
+        }
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
         for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
-            );
+            let dirent = self.read_dirent(disk_inode, i)?;
             if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+                return Ok(Some(dirent.inode_number() as u32));
             }
         }
-        None
+        Ok(None)
     }
 
-    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
-    }
-
-    fn increase_size(
-        &self,
-        new_size: u32,
-        disk_inode: &mut DiskInode,
-        fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    pub fn is_dir(&self) -> FsResult<bool> {
+        let _lock = self.lock.read();
+        self.read_disk_inode(DiskInode::is_dir)
+    }
+
+    pub fn size(&self) -> FsResult<usize> {
+        let _lock = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
+    pub fn find(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        let _lock = self.lock.read();
+        let inode_id =
+            self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode))??;
+        Ok(inode_id.map(|inode_id| EasyFileSystem::get_inode(&self.fs, inode_id)))
+    }
+
+    /// The caller should hold the write lock of this inode.
+    fn increase_size(&self, new_size: usize, disk_inode: &mut DiskInode) -> FsResult {
+        if new_size > MAX_FILE_SIZE {
+            return Err(FsError::NoSpace);
+        }
+        let new_size = new_size as u32;
         if new_size < disk_inode.size {
-            return;
+            return Ok(());
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
+        let mut fs = self.fs.lock();
+        let mut result = Ok(());
         for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+            match fs.alloc_data() {
+                Ok(block_id) => v.push(block_id),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
         }
-        disk_inode.increase_size(new_size, v, &self.block_device);
+        drop(fs);
+        if result.is_ok() {
+            let old = disk_inode.clone();
+            result = disk_inode.increase_size(new_size, v.clone(), &self.block_device);
+            if result.is_err() {
+                *disk_inode = old;
+            }
+        }
+        if result.is_err() {
+            // give the blocks back, the first error is the one reported
+            let mut fs = self.fs.lock();
+            for block_id in v {
+                let _ = fs.dealloc_data(block_id);
+            }
+        }
+        result
     }
 
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
-        let op = |root_inode: &mut DiskInode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
-        };
-        if self.modify_disk_inode(op).is_some() {
-            return None;
+    pub fn create(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    /// Create an empty directory, return `None` if `name` already exists.
+    pub fn mkdir(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> FsResult<Option<Arc<Inode>>> {
+        if !DirEntry::is_valid_name(name) {
+            return Err(FsError::InvalidArgument);
+        }
+        self.check_writable()?;
+        let _lock = self.lock.write();
+        // a removed directory stays empty, its entries could never be freed
+        if self.unlinked.load(Ordering::Acquire) {
+            return Err(FsError::NotFound);
+        }
+        // has the file been created?
+        let op = |root_inode: &DiskInode| self.find_inode_id(name, root_inode);
+        if self.read_disk_inode(op)??.is_some() {
+            return Ok(None);
         }
         // create a new file
         // alloc a inode with an indirect block
-        let new_inode_id = fs.alloc_inode();
-        // initialize inode
-        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+        let (new_inode_id, (new_inode_block_id, new_inode_block_offset), bounds) = {
+            let mut fs = self.fs.lock();
+            let new_inode_id = fs.alloc_inode()?;
+            (
+                new_inode_id,
+                fs.get_disk_inode_pos(new_inode_id),
+                fs.bounds(),
+            )
+        };
+        let linked = get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .and_then(|inode_block| {
+                // initialize inode
+                inode_block
+                    .lock()
+                    .modify(new_inode_block_offset, |bytes: &mut DiskInodeBytes| {
+                        DiskInode::new(type_, bounds).encode(bytes)
+                    });
+                self.modify_disk_inode(|root_inode| {
+                    // append file in the dirent
+                    let file_count = (root_inode.size as usize) / DIRENT_SZ;
+                    let new_size = (file_count + 1) * DIRENT_SZ;
+                    // increase size
+                    self.increase_size(new_size, root_inode)?;
+                    // write dirent
+                    let dirent = DirEntry::new(name, new_inode_id).encode();
+                    root_inode.write_at(file_count * DIRENT_SZ, &dirent, &self.block_device)
+                })?
             });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
-
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
+        if let Err(err) = linked {
+            // nothing refers to the new inode yet
+            let _ = self.fs.lock().dealloc_inode(new_inode_id);
+            return Err(err);
+        }
+
+        block_cache_sync_all()?;
         // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
-        // release efs lock automatically by compiler
+        Ok(Some(EasyFileSystem::get_inode(&self.fs, new_inode_id)))
     }
 
-    pub fn ls(&self) -> Vec<String> {
-        let _fs = self.fs.lock();
+    /// Remove the entry `name`, its inode is freed once the last `Inode` of
+    /// it is dropped. It must be a directory if `expect_dir` is `Some(true)`
+    /// and must not be one if it is `Some(false)`. A directory must be empty.
+    pub fn unlink(&self, name: &str, expect_dir: Option<bool>) -> FsResult {
+        self.check_writable()?;
+        let lock = self.lock.write();
+        let (i, inode_id) = self.read_disk_inode(|dir_inode| {
+            if !dir_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+            for i in 0..file_count {
+                let dirent = self.read_dirent(dir_inode, i)?;
+                if dirent.name() == name {
+                    return Ok((i, dirent.inode_number()));
+                }
+            }
+            Err(FsError::NotFound)
+        })??;
+        if inode_id == self.inode_id {
+            return Err(FsError::Corrupted);
+        }
+        // nothing can be created in it while its lock is held
+        let inode = EasyFileSystem::get_inode(&self.fs, inode_id);
+        let inode_lock = inode.lock.read();
+        let (is_dir, size) =
+            inode.read_disk_inode(|disk_inode| (disk_inode.is_dir(), disk_inode.size))?;
+        match expect_dir {
+            Some(true) if !is_dir => return Err(FsError::NotDir),
+            Some(false) if is_dir => return Err(FsError::IsDir),
+            _ if is_dir && size > 0 => return Err(FsError::NotEmpty),
+            _ => {}
+        }
+        let freed = self.modify_disk_inode(|dir_inode| {
+            // move the last entry into the hole
+            let last = (dir_inode.size as usize) / DIRENT_SZ - 1;
+            if i != last {
+                let dirent = self.read_dirent(dir_inode, last)?.encode();
+                dir_inode.write_at(i * DIRENT_SZ, &dirent, &self.block_device)?;
+            }
+            let blocks = dir_inode.decrease_size((last * DIRENT_SZ) as u32, &self.block_device)?;
+            // the entry is gone, so the inode is unlinked even if this fails
+            Ok(self.fs.lock().dealloc_blocks(blocks))
+        })??;
+        inode.unlinked.store(true, Ordering::Release);
+        drop(inode_lock);
+        drop(lock);
+        let synced = block_cache_sync_all();
+        // freed here unless it is still open
+        drop(inode);
+        freed.and(synced)
+    }
+
+    /// Give back the data, the xattr block and the inode itself.
+    fn free(&self) -> FsResult {
+        self.modify_disk_inode(|disk_inode| {
+            let blocks = disk_inode.clear_size(&self.block_device)?;
+            let mut fs = self.fs.lock();
+            let mut result = fs.dealloc_blocks(blocks);
+            if disk_inode.xattr != 0 {
+                result = result.and(fs.dealloc_data(disk_inode.xattr));
+                disk_inode.xattr = 0;
+            }
+            result.and(fs.dealloc_inode(self.inode_id))
+        })??;
+        block_cache_sync_all()
+    }
+
+    pub fn ls(&self) -> FsResult<Vec<String>> {
+        let _lock = self.lock.read();
         self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
             let file_count = (disk_inode.size as usize) / DIRENT_SZ;
             let mut v: Vec<String> = Vec::new();
             for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
+                v.push(String::from(self.read_dirent(disk_inode, i)?.name()));
             }
-            v
-        })
+            Ok(v)
+        })?
     }
 
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let _lock = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))?
     }
 
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
-        size
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        self.check_writable()?;
+        let lock = self.lock.write();
+        let size =
+            self.modify_disk_inode(|disk_inode| self.write_locked(disk_inode, offset, buf))??;
+        drop(lock);
+        block_cache_sync_all()?;
+        Ok(size)
     }
 
-    pub fn clear(&self) {
-        let mut fs = self.fs.lock();
+    /// Write at the end, under the lock so appends do not overwrite each
+    /// other. Return the offset `buf` went to.
+    pub fn append(&self, buf: &[u8]) -> FsResult<usize> {
+        self.check_writable()?;
+        let lock = self.lock.write();
+        let offset = self.modify_disk_inode(|disk_inode| {
+            let offset = disk_inode.size as usize;
+            self.write_locked(disk_inode, offset, buf)?;
+            Ok(offset)
+        })??;
+        drop(lock);
+        block_cache_sync_all()?;
+        Ok(offset)
+    }
+
+    /// The caller should hold the write lock of this inode.
+    fn write_locked(
+        &self,
+        disk_inode: &mut DiskInode,
+        offset: usize,
+        buf: &[u8],
+    ) -> FsResult<usize> {
+        let new_size = offset.checked_add(buf.len()).ok_or(FsError::NoSpace)?;
+        self.increase_size(new_size, disk_inode)?;
+        disk_inode.write_at(offset, buf, &self.block_device)
+    }
+
+    pub fn clear(&self) -> FsResult {
+        self.check_writable()?;
+        let lock = self.lock.write();
         self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
-            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
+            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device)?;
             assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data(data_block);
+            self.fs.lock().dealloc_blocks(data_blocks_dealloc)
+        })??;
+        drop(lock);
+        block_cache_sync_all()?;
+        Ok(())
+    }
+
+    fn read_xattrs(&self, disk_inode: &DiskInode) -> FsResult<Vec<(String, Vec<u8>)>> {
+        if disk_inode.xattr == 0 {
+            return Ok(Vec::new());
+        }
+        get_block_cache(disk_inode.xattr as usize, Arc::clone(&self.block_device))?
+            .lock()
+            .read_checked(0, decode_xattrs)?
+    }
+
+    /// The xattr block is allocated on first use and freed when it becomes empty.
+    fn write_xattrs(&self, disk_inode: &mut DiskInode, xattrs: &[(String, Vec<u8>)]) -> FsResult {
+        if xattrs.is_empty() {
+            if disk_inode.xattr != 0 {
+                self.fs.lock().dealloc_data(disk_inode.xattr)?;
+                disk_inode.xattr = 0;
             }
-        });
-        block_cache_sync_all();
+            return Ok(());
+        }
+        let block = encode_xattrs(xattrs)?;
+        if disk_inode.xattr == 0 {
+            disk_inode.xattr = self.fs.lock().alloc_data()?;
+        }
+        get_block_cache(disk_inode.xattr as usize, Arc::clone(&self.block_device))?
+            .lock()
+            .modify(0, |xattr_block: &mut XattrBlock| *xattr_block = block);
+        Ok(())
+    }
+
+    pub fn get_xattr(&self, name: &str) -> FsResult<Vec<u8>> {
+        let _lock = self.lock.read();
+        let xattrs = self.read_disk_inode(|disk_inode| self.read_xattrs(disk_inode))??;
+        xattrs
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or(FsError::NotFound)
+    }
+
+    /// Add the attribute or replace its value, as far as `mode` allows.
+    /// Return false if `mode` did not allow it.
+    pub fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> FsResult<bool> {
+        if name.is_empty() || name.len() > XATTR_NAME_LIMIT {
+            return Err(FsError::InvalidArgument);
+        }
+        self.check_writable()?;
+        let lock = self.lock.write();
+        let stored = self.modify_disk_inode(|disk_inode| {
+            let mut xattrs = self.read_xattrs(disk_inode)?;
+            match (xattrs.iter_mut().find(|(key, _)| key == name), mode) {
+                (Some(_), XattrMode::Create) | (None, XattrMode::Replace) => return Ok(false),
+                (Some((_, old)), _) => *old = value.to_vec(),
+                (None, _) => xattrs.push((String::from(name), value.to_vec())),
+            }
+            self.write_xattrs(disk_inode, &xattrs)?;
+            Ok(true)
+        })??;
+        drop(lock);
+        if stored {
+            block_cache_sync_all()?;
+        }
+        Ok(stored)
+    }
+
+    pub fn list_xattr(&self) -> FsResult<Vec<String>> {
+        let _lock = self.lock.read();
+        let xattrs = self.read_disk_inode(|disk_inode| self.read_xattrs(disk_inode))??;
+        Ok(xattrs.into_iter().map(|(name, _)| name).collect())
+    }
+
+    pub fn remove_xattr(&self, name: &str) -> FsResult {
+        self.check_writable()?;
+        let lock = self.lock.write();
+        self.modify_disk_inode(|disk_inode| {
+            let mut xattrs = self.read_xattrs(disk_inode)?;
+            let pos = xattrs
+                .iter()
+                .position(|(key, _)| key == name)
+                .ok_or(FsError::NotFound)?;
+            xattrs.remove(pos);
+            self.write_xattrs(disk_inode, &xattrs)
+        })??;
+        drop(lock);
+        block_cache_sync_all()?;
+        Ok(())
+    }
+}
+
+impl Drop for Inode {
+    /// There is nobody to report an error to, the inode then stays allocated.
+    fn drop(&mut self) {
+        if self.unlinked.load(Ordering::Acquire) {
+            let _ = self.free();
+        }
     }
 }
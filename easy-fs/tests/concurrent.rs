@@ -0,0 +1,92 @@
+//! Write, clear and sync several files from many threads at once.
+
+use easy_fs::testing::RamDisk;
+use easy_fs::{EasyFileSystem, XattrMode, BLOCK_SZ};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const TOTAL_BLOCKS: u32 = 8192;
+const THREADS: usize = 4;
+const ROUNDS: u8 = 5;
+/// Large enough to reach the doubly indirect blocks.
+const FILE_SIZE: usize = 200 * BLOCK_SZ;
+
+#[test]
+fn writers_do_not_deadlock() {
+    let disk = Arc::new(RamDisk::new(TOTAL_BLOCKS as usize));
+    EasyFileSystem::create(disk.clone(), TOTAL_BLOCKS, 1).unwrap();
+    let efs = EasyFileSystem::open(disk).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let (done, finished) = mpsc::channel();
+    for i in 0..THREADS {
+        let inode = root.create(&format!("file{}", i)).unwrap().unwrap();
+        let done = done.clone();
+        thread::spawn(move || {
+            let data = vec![i as u8; FILE_SIZE];
+            for round in 0..ROUNDS {
+                // every write and clear syncs all blocks, while the others
+                // are filling their indirect blocks
+                assert_eq!(inode.write_at(0, &data).unwrap(), FILE_SIZE);
+                inode
+                    .set_xattr("user.round", &[round], XattrMode::Set)
+                    .unwrap();
+                assert_eq!(inode.size().unwrap(), FILE_SIZE);
+                let mut buf = [0u8; BLOCK_SZ];
+                assert_eq!(
+                    inode.read_at(FILE_SIZE - BLOCK_SZ, &mut buf).unwrap(),
+                    BLOCK_SZ
+                );
+                assert_eq!(buf, [i as u8; BLOCK_SZ]);
+                inode.clear().unwrap();
+            }
+            done.send(()).unwrap();
+        });
+    }
+    for _ in 0..THREADS {
+        finished
+            .recv_timeout(Duration::from_secs(300))
+            .expect("writers are stuck or have panicked");
+    }
+    for i in 0..THREADS {
+        let inode = root.find(&format!("file{}", i)).unwrap().unwrap();
+        assert_eq!(inode.size().unwrap(), 0);
+        assert_eq!(inode.get_xattr("user.round").unwrap(), [ROUNDS - 1]);
+    }
+}
+
+#[test]
+fn appends_do_not_overwrite_each_other() {
+    const APPENDS: usize = 50;
+    /// Not a divisor of the block size, so records straddle blocks.
+    const RECORD: usize = 100;
+    let disk = Arc::new(RamDisk::new(TOTAL_BLOCKS as usize));
+    EasyFileSystem::create(disk.clone(), TOTAL_BLOCKS, 1).unwrap();
+    let efs = EasyFileSystem::open(disk).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let log = root.create("log").unwrap().unwrap();
+    let writers: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let log = log.clone();
+            thread::spawn(move || {
+                for _ in 0..APPENDS {
+                    let offset = log.append(&[i as u8; RECORD]).unwrap();
+                    assert_eq!(offset % RECORD, 0);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    let mut data = vec![0u8; THREADS * APPENDS * RECORD];
+    assert_eq!(log.size().unwrap(), data.len());
+    assert_eq!(log.read_at(0, &mut data).unwrap(), data.len());
+    let mut counts = [0; THREADS];
+    for record in data.chunks(RECORD) {
+        assert!(record.iter().all(|&b| b == record[0]));
+        counts[record[0] as usize] += 1;
+    }
+    assert_eq!(counts, [APPENDS; THREADS]);
+}
@@ -0,0 +1,345 @@
+//! Run random operations against easy-fs and a simple in-memory model.
+
+use easy_fs::testing::{power_off, Fault, FaultyDevice, RamDisk};
+use easy_fs::{BlockDevice, EasyFileSystem, FsError, FsResult, XattrMode, BLOCK_SZ};
+use proptest::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+const TOTAL_BLOCKS: u32 = 4096;
+const NAMES: [&str; 4] = ["a", "b", "c", "long_file_name_of_27_bytes_"];
+/// Large enough to reach the doubly indirect blocks.
+const MAX_OFFSET: usize = 200 * BLOCK_SZ;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Create(usize),
+    Write(usize, usize, Vec<u8>),
+    Read(usize, usize, usize),
+    Clear(usize),
+    Unlink(usize),
+}
+
+impl Op {
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Create(i)
+            | Op::Write(i, _, _)
+            | Op::Read(i, _, _)
+            | Op::Clear(i)
+            | Op::Unlink(i) => NAMES[*i],
+        }
+    }
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let name = 0..NAMES.len();
+    prop_oneof![
+        name.clone().prop_map(Op::Create),
+        (
+            name.clone(),
+            0..MAX_OFFSET,
+            prop::collection::vec(any::<u8>(), 0..3 * BLOCK_SZ)
+        )
+            .prop_map(|(i, offset, data)| Op::Write(i, offset, data)),
+        (name.clone(), 0..MAX_OFFSET, 0..3 * BLOCK_SZ)
+            .prop_map(|(i, offset, len)| Op::Read(i, offset, len)),
+        name.clone().prop_map(Op::Clear),
+        name.prop_map(Op::Unlink),
+    ]
+}
+
+type Model = BTreeMap<&'static str, Vec<u8>>;
+
+/// Apply `op` to both sides and check that they agree if the filesystem succeeds.
+fn apply(root: &easy_fs::Inode, model: &mut Model, op: &Op) -> FsResult {
+    let name = op.name();
+    match op {
+        Op::Create(_) => {
+            let created = root.create(name)?;
+            assert_eq!(created.is_some(), !model.contains_key(name));
+            model.entry(name).or_default();
+        }
+        Op::Write(_, offset, data) => {
+            let inode = root.find(name)?;
+            let content = match model.get_mut(name) {
+                Some(content) => content,
+                None => {
+                    assert!(inode.is_none());
+                    return Ok(());
+                }
+            };
+            assert_eq!(inode.unwrap().write_at(*offset, data)?, data.len());
+            let end = offset + data.len();
+            if content.len() < end {
+                content.resize(end, 0);
+            }
+            content[*offset..end].copy_from_slice(data);
+        }
+        Op::Read(_, offset, len) => {
+            let inode = root.find(name)?;
+            let content = match model.get(name) {
+                Some(content) => content,
+                None => {
+                    assert!(inode.is_none());
+                    return Ok(());
+                }
+            };
+            let mut buf = vec![0u8; *len];
+            let read = inode.unwrap().read_at(*offset, &mut buf)?;
+            let expected = content.get(*offset..).unwrap_or(&[]);
+            let expected = &expected[..expected.len().min(*len)];
+            assert_eq!(&buf[..read], expected);
+        }
+        Op::Clear(_) => {
+            let inode = root.find(name)?;
+            if let Some(content) = model.get_mut(name) {
+                inode.unwrap().clear()?;
+                content.clear();
+            } else {
+                assert!(inode.is_none());
+            }
+        }
+        Op::Unlink(_) => match root.unlink(name, Some(false)) {
+            Err(FsError::NotFound) => assert!(!model.contains_key(name)),
+            result => {
+                result?;
+                assert!(model.remove(name).is_some());
+            }
+        },
+    }
+    Ok(())
+}
+
+fn read_all(inode: &easy_fs::Inode) -> Vec<u8> {
+    let mut content = Vec::new();
+    let mut buf = [0u8; BLOCK_SZ];
+    loop {
+        let len = inode.read_at(content.len(), &mut buf).unwrap();
+        if len == 0 {
+            return content;
+        }
+        content.extend_from_slice(&buf[..len]);
+    }
+}
+
+/// Check the files in `model`, ignoring those in `skip`.
+fn check(root: &easy_fs::Inode, model: &Model, skip: &BTreeSet<&str>) {
+    for name in NAMES.iter().filter(|name| !skip.contains(*name)) {
+        let inode = root.find(name).unwrap();
+        match model.get(name) {
+            Some(content) => assert_eq!(&read_all(&inode.unwrap()), content),
+            None => assert!(inode.is_none()),
+        }
+    }
+}
+
+fn new_disk() -> Arc<RamDisk> {
+    let disk = Arc::new(RamDisk::new(TOTAL_BLOCKS as usize));
+    EasyFileSystem::create(disk.clone(), TOTAL_BLOCKS, 1).unwrap();
+    disk
+}
+
+#[test]
+fn failed_write_frees_blocks() {
+    let disk = new_disk();
+    let efs = EasyFileSystem::open(disk).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let big = root.create("big").unwrap().unwrap();
+    let too_much = vec![1u8; TOTAL_BLOCKS as usize * BLOCK_SZ];
+    assert_eq!(big.write_at(0, &too_much), Err(FsError::NoSpace));
+    assert_eq!(big.size().unwrap(), 0);
+    // every free block is still there
+    let fits = &too_much[..too_much.len() / 2];
+    assert_eq!(big.write_at(0, fits).unwrap(), fits.len());
+}
+
+#[test]
+fn failed_create_frees_inode() {
+    let disk = new_disk();
+    let efs = EasyFileSystem::open(disk).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    // fill the first directory block of 32-byte entries, the next one needs a new block
+    let dirents_per_block = BLOCK_SZ / 32;
+    for i in 0..dirents_per_block {
+        root.create(&format!("f{}", i)).unwrap().unwrap();
+    }
+    let big = root.find("f0").unwrap().unwrap();
+    let mut offset = 0;
+    while big.write_at(offset, &[1u8; BLOCK_SZ]).is_ok() {
+        offset += BLOCK_SZ;
+    }
+    assert_eq!(root.create("last").map(|_| ()), Err(FsError::NoSpace));
+    big.clear().unwrap();
+    let last = root.create("last").unwrap().unwrap();
+    assert_eq!(last.inode_id() as usize, dirents_per_block + 1);
+}
+
+#[test]
+fn xattr_modes() {
+    let efs = EasyFileSystem::open(new_disk()).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let file = root.create("f").unwrap().unwrap();
+    assert!(!file.set_xattr("user.x", b"a", XattrMode::Replace).unwrap());
+    assert_eq!(file.get_xattr("user.x"), Err(FsError::NotFound));
+    assert!(file.set_xattr("user.x", b"a", XattrMode::Create).unwrap());
+    assert!(!file.set_xattr("user.x", b"b", XattrMode::Create).unwrap());
+    assert_eq!(file.get_xattr("user.x").unwrap(), b"a");
+    assert!(file.set_xattr("user.x", b"c", XattrMode::Replace).unwrap());
+    assert!(file.set_xattr("user.x", b"d", XattrMode::Set).unwrap());
+    assert_eq!(file.get_xattr("user.x").unwrap(), b"d");
+}
+
+#[test]
+fn live_inodes_counts_open_inodes() {
+    let efs = EasyFileSystem::open(new_disk()).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    // the root and `efs` itself
+    assert_eq!(root.live_inodes(), 2);
+    let file = root.create("f").unwrap().unwrap();
+    assert_eq!(root.live_inodes(), 3);
+    drop(file);
+    assert_eq!(root.live_inodes(), 2);
+}
+
+/// Write whole blocks until the disk is full, return how much fit.
+fn fill(inode: &easy_fs::Inode) -> usize {
+    let mut size = 0;
+    while inode.write_at(size, &[1u8; BLOCK_SZ]).is_ok() {
+        size += BLOCK_SZ;
+    }
+    size
+}
+
+#[test]
+fn unlink_frees_everything() {
+    let efs = EasyFileSystem::open(new_disk()).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let free_space = fill(&root.create("probe").unwrap().unwrap());
+
+    let efs = EasyFileSystem::open(new_disk()).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    // enough entries for the directory to reach its doubly indirect blocks
+    let names: Vec<String> = (0..2500).map(|i| format!("f{}", i)).collect();
+    for name in names.iter() {
+        root.create(name).unwrap().unwrap();
+    }
+    let open = root.find("f7").unwrap().unwrap();
+    open.write_at(0, &[7u8; 3 * BLOCK_SZ]).unwrap();
+    open.set_xattr("user.x", b"y", XattrMode::Set).unwrap();
+    // each removal moves the last entry into the hole
+    for name in names.iter() {
+        root.unlink(name, Some(false)).unwrap();
+    }
+    assert!(root.ls().unwrap().is_empty());
+    assert_eq!(root.size().unwrap(), 0);
+    assert_eq!(root.unlink("f0", None), Err(FsError::NotFound));
+    // still there while it is open
+    let mut buf = [0u8; BLOCK_SZ];
+    assert_eq!(open.read_at(2 * BLOCK_SZ, &mut buf).unwrap(), BLOCK_SZ);
+    assert_eq!(buf, [7u8; BLOCK_SZ]);
+    drop(open);
+
+    let probe = root.create("probe").unwrap().unwrap();
+    assert_eq!(probe.inode_id(), 1);
+    assert_eq!(fill(&probe), free_space);
+}
+
+#[test]
+fn unlink_checks_the_entry() {
+    let efs = EasyFileSystem::open(new_disk()).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let dir = root.mkdir("d").unwrap().unwrap();
+    dir.create("f").unwrap().unwrap();
+    assert_eq!(root.unlink("d", Some(false)), Err(FsError::IsDir));
+    assert_eq!(root.unlink("d", Some(true)), Err(FsError::NotEmpty));
+    assert_eq!(dir.unlink("f", Some(true)), Err(FsError::NotDir));
+    dir.unlink("f", Some(false)).unwrap();
+    root.unlink("d", Some(true)).unwrap();
+    // an open directory which was removed stays empty
+    assert_eq!(dir.create("g").map(|_| ()), Err(FsError::NotFound));
+}
+
+#[test]
+fn failed_discard_still_frees_blocks() {
+    // discards fail from the first write on, writes keep working
+    let device = Arc::new(FaultyDevice::new(new_disk(), 0, Fault::Discard));
+    let efs = EasyFileSystem::open(device).unwrap();
+    let root = EasyFileSystem::root_inode(&efs);
+    let free_space = fill(&root.create("probe").unwrap().unwrap());
+    // the directory block and the blocks of the file are freed all the same
+    assert_eq!(root.unlink("probe", None), Err(FsError::Io));
+    assert!(root.ls().unwrap().is_empty());
+    let probe = root.create("probe").unwrap().unwrap();
+    assert_eq!(probe.inode_id(), 1);
+    assert_eq!(fill(&probe), free_space);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn matches_model(ops in prop::collection::vec(op(), 1..40)) {
+        let disk = new_disk();
+        let efs = EasyFileSystem::open(disk.clone()).unwrap();
+        let root = EasyFileSystem::root_inode(&efs);
+        let mut model = Model::new();
+        for op in &ops {
+            apply(&root, &mut model, op).unwrap();
+        }
+        check(&root, &model, &BTreeSet::new());
+        // everything has been synced, a fresh copy must hold the same files
+        let disk: Arc<dyn BlockDevice> = Arc::new(disk.snapshot());
+        let efs = EasyFileSystem::open(disk).unwrap();
+        check(&EasyFileSystem::root_inode(&efs), &model, &BTreeSet::new());
+    }
+
+    #[test]
+    fn crash_and_remount(
+        ops in prop::collection::vec(op(), 1..40),
+        good_writes in 0usize..400,
+        fault in prop_oneof![Just(Fault::Fail), Just(Fault::Drop), Just(Fault::Tear)],
+    ) {
+        let disk = new_disk();
+        let device = Arc::new(FaultyDevice::new(disk.clone(), good_writes, fault));
+        let efs = EasyFileSystem::open(device.clone()).unwrap();
+        let root = EasyFileSystem::root_inode(&efs);
+        // stop at the first lost write, the file touched by that operation is
+        // in an unknown state
+        let mut model = Model::new();
+        let mut torn = None;
+        for op in &ops {
+            let mut next = model.clone();
+            let result = apply(&root, &mut next, op);
+            if device.has_failed() {
+                // every write syncs, so a failing device must be noticed
+                if fault == Fault::Fail {
+                    assert_eq!(result, Err(FsError::Io));
+                }
+                torn = Some(op);
+                break;
+            }
+            result.unwrap();
+            model = next;
+        }
+        drop(root);
+        drop(efs);
+        power_off(&(device as Arc<dyn BlockDevice>));
+        // remount what reached the disk
+        let disk: Arc<dyn BlockDevice> = Arc::new(disk.snapshot());
+        let efs = EasyFileSystem::open(disk).unwrap();
+        let root = EasyFileSystem::root_inode(&efs);
+        match torn {
+            Some(Op::Create(_)) | Some(Op::Unlink(_)) => {
+                // the directory may be torn, lookups only must not panic
+                for name in NAMES.iter() {
+                    if let Ok(Some(inode)) = root.find(name) {
+                        let _ = inode.read_at(0, &mut [0u8; BLOCK_SZ]);
+                    }
+                }
+            }
+            Some(op) => check(&root, &model, &[op.name()].iter().copied().collect()),
+            None => check(&root, &model, &BTreeSet::new()),
+        }
+    }
+}
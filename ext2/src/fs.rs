@@ -0,0 +1,121 @@
+use super::{
+    block_cache_release, get_block_cache, read_u32, BlockDevice, DiskInode, FsError, FsResult,
+    Inode, SuperBlock, BLOCK_SZ, DIRECT_BLOCKS, GROUP_DESC_SIZE, ROOT_INODE, SUPERBLOCK_OFFSET,
+    SUPERBLOCK_SIZE,
+};
+use alloc::sync::Arc;
+
+/// Never written to, so there is nothing to lock.
+pub struct Ext2FileSystem {
+    pub block_device: Arc<dyn BlockDevice>,
+    pub sb: SuperBlock,
+}
+
+impl Ext2FileSystem {
+    /// Fail with `FsError::Unsupported` unless the device holds plain ext2.
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> FsResult<Arc<Self>> {
+        let mut buf = [0u8; SUPERBLOCK_SIZE];
+        read_device(&block_device, SUPERBLOCK_OFFSET, &mut buf)?;
+        let sb = SuperBlock::parse(&buf)?;
+        Ok(Arc::new(Self { block_device, sb }))
+    }
+    /// Drop the cached blocks, none of them are dirty.
+    pub fn unmount(block_device: &Arc<dyn BlockDevice>) -> FsResult {
+        block_cache_release(block_device)
+    }
+    pub fn root_inode(fs: &Arc<Self>) -> Arc<Inode> {
+        Arc::new(Inode::new(ROOT_INODE, fs.clone()))
+    }
+    pub fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> FsResult {
+        read_device(&self.block_device, offset, buf)
+    }
+    /// Read from filesystem block `block`, which must exist.
+    pub fn read_block(&self, block: u32, offset: usize, buf: &mut [u8]) -> FsResult {
+        if block >= self.sb.blocks_count || offset + buf.len() > self.sb.block_size {
+            return Err(FsError::Corrupted);
+        }
+        self.read_bytes(
+            block as u64 * self.sb.block_size as u64 + offset as u64,
+            buf,
+        )
+    }
+    pub fn disk_inode(&self, inode_id: u32) -> FsResult<DiskInode> {
+        if inode_id == 0 || inode_id > self.sb.inodes_count {
+            return Err(FsError::Corrupted);
+        }
+        let group = (inode_id - 1) / self.sb.inodes_per_group;
+        let index = ((inode_id - 1) % self.sb.inodes_per_group) as u64;
+        // the group descriptors follow the superblock's block
+        let desc_offset = (self.sb.first_data_block as u64 + 1) * self.sb.block_size as u64
+            + group as u64 * GROUP_DESC_SIZE as u64;
+        let mut desc = [0u8; GROUP_DESC_SIZE];
+        self.read_bytes(desc_offset, &mut desc)?;
+        let inode_table = read_u32(&desc, 8);
+        if inode_table == 0 || inode_table >= self.sb.blocks_count {
+            return Err(FsError::Corrupted);
+        }
+        let mut buf = [0u8; 128];
+        self.read_bytes(
+            inode_table as u64 * self.sb.block_size as u64 + index * self.sb.inode_size as u64,
+            &mut buf,
+        )?;
+        Ok(DiskInode::parse(&buf, self.sb.large_file))
+    }
+    /// The block holding block `index` of the file, 0 for a hole.
+    pub fn data_block(&self, inode: &DiskInode, index: u64) -> FsResult<u32> {
+        let per_block = (self.sb.block_size / 4) as u64;
+        let mut index = index;
+        if index < DIRECT_BLOCKS as u64 {
+            return Ok(inode.block[index as usize]);
+        }
+        index -= DIRECT_BLOCKS as u64;
+        if index < per_block {
+            return self.indirect(inode.block[12], &[index]);
+        }
+        index -= per_block;
+        if index < per_block * per_block {
+            return self.indirect(inode.block[13], &[index / per_block, index % per_block]);
+        }
+        index -= per_block * per_block;
+        if index < per_block * per_block * per_block {
+            return self.indirect(
+                inode.block[14],
+                &[
+                    index / (per_block * per_block),
+                    index / per_block % per_block,
+                    index % per_block,
+                ],
+            );
+        }
+        Err(FsError::Corrupted)
+    }
+    /// Follow `path` down a tree of indirect blocks.
+    fn indirect(&self, mut block: u32, path: &[u64]) -> FsResult<u32> {
+        for &index in path {
+            if block == 0 {
+                return Ok(0);
+            }
+            let mut entry = [0u8; 4];
+            self.read_block(block, index as usize * 4, &mut entry)?;
+            block = u32::from_le_bytes(entry);
+        }
+        Ok(block)
+    }
+}
+
+/// Fill `buf` from the device, starting `offset` bytes in.
+fn read_device(block_device: &Arc<dyn BlockDevice>, offset: u64, buf: &mut [u8]) -> FsResult {
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let block_offset = (pos % BLOCK_SZ as u64) as usize;
+        let len = (BLOCK_SZ - block_offset).min(buf.len() - done);
+        get_block_cache((pos / BLOCK_SZ as u64) as usize, block_device.clone())?
+            .lock()
+            .read(0, |data: &[u8; BLOCK_SZ]| {
+                buf[done..done + len].copy_from_slice(&data[block_offset..block_offset + len])
+            });
+        done += len;
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use super::{FsError, FsResult};
+
+/// The superblock is always 1024 bytes into the device.
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+pub const EXT2_MAGIC: u16 = 0xef53;
+pub const ROOT_INODE: u32 = 2;
+pub const GROUP_DESC_SIZE: usize = 32;
+/// Direct block pointers, then singly, doubly and triply indirect ones.
+pub const DIRECT_BLOCKS: usize = 12;
+
+/// Directory entries carry the file type.
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Needs a 64 bit size for regular files.
+const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+const S_IFMT: u16 = 0xf000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xa000;
+
+pub fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// The fields of the superblock we need.
+#[derive(Clone, Copy, Debug)]
+pub struct SuperBlock {
+    pub inodes_count: u32,
+    pub blocks_count: u32,
+    pub first_data_block: u32,
+    pub block_size: usize,
+    pub inodes_per_group: u32,
+    pub inode_size: usize,
+    pub large_file: bool,
+}
+
+impl SuperBlock {
+    /// Anything with features beyond ext2 and the file type in directory
+    /// entries is refused, ext3 journals and ext4 extents included.
+    pub fn parse(buf: &[u8; SUPERBLOCK_SIZE]) -> FsResult<Self> {
+        if read_u16(buf, 56) != EXT2_MAGIC {
+            return Err(FsError::Unsupported);
+        }
+        let rev_level = read_u32(buf, 76);
+        let (inode_size, incompat, ro_compat) = if rev_level == 0 {
+            (128, 0, 0)
+        } else {
+            (
+                read_u16(buf, 88) as usize,
+                read_u32(buf, 96),
+                read_u32(buf, 100),
+            )
+        };
+        if incompat & !INCOMPAT_FILETYPE != 0 {
+            return Err(FsError::Unsupported);
+        }
+        let log_block_size = read_u32(buf, 24);
+        if log_block_size > 6 {
+            return Err(FsError::Corrupted);
+        }
+        let sb = Self {
+            inodes_count: read_u32(buf, 0),
+            blocks_count: read_u32(buf, 4),
+            first_data_block: read_u32(buf, 20),
+            block_size: 1024 << log_block_size,
+            inodes_per_group: read_u32(buf, 40),
+            inode_size,
+            large_file: ro_compat & RO_COMPAT_LARGE_FILE != 0,
+        };
+        if sb.inodes_per_group == 0
+            || sb.inode_size < 128
+            || !sb.inode_size.is_power_of_two()
+            || sb.inode_size > sb.block_size
+            || read_u32(buf, 32) == 0
+        {
+            return Err(FsError::Corrupted);
+        }
+        Ok(sb)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, fifos and sockets, which can only be listed.
+    Other,
+}
+
+/// The fields of an on-disk inode we need.
+#[derive(Clone, Copy, Debug)]
+pub struct DiskInode {
+    pub mode: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub links_count: u16,
+    pub size: u64,
+    /// Seconds since the epoch.
+    pub atime: u32,
+    pub ctime: u32,
+    pub mtime: u32,
+    /// In 512 byte units, counting the xattr block too.
+    pub sectors: u32,
+    pub block: [u32; 15],
+    /// The xattr block, 0 if there is none.
+    pub file_acl: u32,
+}
+
+impl DiskInode {
+    pub fn parse(buf: &[u8], large_file: bool) -> Self {
+        let mut block = [0u32; 15];
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = read_u32(buf, 40 + i * 4);
+        }
+        let mode = read_u16(buf, 0);
+        let mut size = read_u32(buf, 4) as u64;
+        // the high half shares its place with the directory ACL
+        if large_file && mode & S_IFMT == S_IFREG {
+            size |= (read_u32(buf, 108) as u64) << 32;
+        }
+        Self {
+            mode,
+            uid: read_u16(buf, 2),
+            gid: read_u16(buf, 24),
+            links_count: read_u16(buf, 26),
+            size,
+            atime: read_u32(buf, 8),
+            ctime: read_u32(buf, 12),
+            mtime: read_u32(buf, 16),
+            sectors: read_u32(buf, 28),
+            block,
+            file_acl: read_u32(buf, 104),
+        }
+    }
+    pub fn file_type(&self) -> FileType {
+        match self.mode & S_IFMT {
+            S_IFREG => FileType::File,
+            S_IFDIR => FileType::Dir,
+            S_IFLNK => FileType::Symlink,
+            _ => FileType::Other,
+        }
+    }
+    /// Short symlink targets are kept in `block` and use no data blocks,
+    /// apart from an xattr block.
+    pub fn is_fast_symlink(&self, block_size: usize) -> bool {
+        let xattr_sectors = if self.file_acl != 0 {
+            block_size / 512
+        } else {
+            0
+        };
+        self.file_type() == FileType::Symlink && self.sectors as usize == xattr_sectors
+    }
+}
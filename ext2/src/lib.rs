@@ -0,0 +1,16 @@
+//! Read-only ext2, through the block cache of easy-fs.
+
+#![no_std]
+
+extern crate alloc;
+
+mod fs;
+mod layout;
+mod vfs;
+
+pub use fs::Ext2FileSystem;
+pub use vfs::{Inode, Metadata};
+
+use easy_fs::{block_cache_release, get_block_cache, BlockDevice, FsError, FsResult, BLOCK_SZ};
+pub use layout::FileType;
+use layout::*;
@@ -0,0 +1,154 @@
+use super::{read_u16, read_u32, DiskInode, Ext2FileSystem, FileType, FsError, FsResult};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Inode fields for `stat`, times are seconds since the epoch.
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub mode: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub nlink: u16,
+    /// In 512 byte units.
+    pub blocks: u32,
+    pub block_size: usize,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+}
+
+pub struct Inode {
+    inode_id: u32,
+    fs: Arc<Ext2FileSystem>,
+}
+
+impl Inode {
+    pub fn new(inode_id: u32, fs: Arc<Ext2FileSystem>) -> Self {
+        Self { inode_id, fs }
+    }
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+    /// How many inodes of the filesystem are alive, this one included.
+    /// Other handles on the filesystem count too.
+    pub fn live_inodes(&self) -> usize {
+        Arc::strong_count(&self.fs)
+    }
+    fn disk_inode(&self) -> FsResult<DiskInode> {
+        self.fs.disk_inode(self.inode_id)
+    }
+    pub fn file_type(&self) -> FsResult<FileType> {
+        Ok(self.disk_inode()?.file_type())
+    }
+    pub fn is_dir(&self) -> FsResult<bool> {
+        Ok(self.file_type()? == FileType::Dir)
+    }
+    pub fn size(&self) -> FsResult<usize> {
+        Ok(self.disk_inode()?.size as usize)
+    }
+    pub fn metadata(&self) -> FsResult<Metadata> {
+        let inode = self.disk_inode()?;
+        Ok(Metadata {
+            mode: inode.mode,
+            uid: inode.uid,
+            gid: inode.gid,
+            nlink: inode.links_count,
+            blocks: inode.sectors,
+            block_size: self.fs.sb.block_size,
+            atime: inode.atime,
+            mtime: inode.mtime,
+            ctime: inode.ctime,
+        })
+    }
+    /// Whatever the type, holes read as zeroes.
+    fn read_data(&self, inode: &DiskInode, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let block_size = self.fs.sb.block_size;
+        let end = (inode.size.min((offset + buf.len()) as u64)) as usize;
+        let mut pos = offset;
+        while pos < end {
+            let block_offset = pos % block_size;
+            let len = (block_size - block_offset).min(end - pos);
+            let dest = &mut buf[pos - offset..pos - offset + len];
+            match self.fs.data_block(inode, (pos / block_size) as u64)? {
+                0 => dest.fill(0),
+                block => self.fs.read_block(block, block_offset, dest)?,
+            }
+            pos += len;
+        }
+        Ok(end.saturating_sub(offset))
+    }
+    /// Only regular files can be read.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let inode = self.disk_inode()?;
+        if inode.file_type() != FileType::File {
+            return Err(FsError::InvalidArgument);
+        }
+        self.read_data(&inode, offset, buf)
+    }
+    /// Entries other than "." and "..", as names and inode numbers. They never
+    /// cross a block, so one block is read at a time.
+    fn entries(&self) -> FsResult<Vec<(String, u32)>> {
+        let inode = self.disk_inode()?;
+        if inode.file_type() != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let block_size = self.fs.sb.block_size;
+        if inode.size > self.fs.sb.blocks_count as u64 * block_size as u64 {
+            return Err(FsError::Corrupted);
+        }
+        let mut data = vec![0u8; block_size];
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset < inode.size as usize {
+            let len = self.read_data(&inode, offset, &mut data)?;
+            let block = &data[..len];
+            let mut pos = 0;
+            while pos + 8 <= block.len() {
+                let inode_id = read_u32(block, pos);
+                let rec_len = read_u16(block, pos + 4) as usize;
+                let name_len = block[pos + 6] as usize;
+                if rec_len < 8 || pos + rec_len > block.len() || 8 + name_len > rec_len {
+                    return Err(FsError::Corrupted);
+                }
+                let name = &block[pos + 8..pos + 8 + name_len];
+                if inode_id != 0 && name != b"." && name != b".." {
+                    entries.push((String::from_utf8_lossy(name).into_owned(), inode_id));
+                }
+                pos += rec_len;
+            }
+            offset += len;
+        }
+        Ok(entries)
+    }
+    pub fn find(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, inode_id)| Arc::new(Inode::new(inode_id, self.fs.clone()))))
+    }
+    pub fn ls(&self) -> FsResult<Vec<String>> {
+        Ok(self.entries()?.into_iter().map(|(name, _)| name).collect())
+    }
+    /// The target if this is a symbolic link, which takes one block at most.
+    pub fn read_link(&self) -> FsResult<Option<String>> {
+        let inode = self.disk_inode()?;
+        if inode.file_type() != FileType::Symlink {
+            return Ok(None);
+        }
+        if inode.size > self.fs.sb.block_size as u64 {
+            return Err(FsError::Corrupted);
+        }
+        let mut target = vec![0u8; inode.size as usize];
+        if inode.is_fast_symlink(self.fs.sb.block_size) {
+            let bytes: Vec<u8> = inode.block.iter().flat_map(|b| b.to_le_bytes()).collect();
+            let len = target.len().min(bytes.len());
+            target[..len].copy_from_slice(&bytes[..len]);
+        } else {
+            self.read_data(&inode, 0, &mut target)?;
+        }
+        Ok(Some(String::from_utf8_lossy(&target).into_owned()))
+    }
+}
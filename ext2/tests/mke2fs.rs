@@ -0,0 +1,250 @@
+//! Images made by `mke2fs -d` from a directory tree on the host.
+
+use easy_fs::testing::RamDisk;
+use easy_fs::{BlockDevice, FsError, BLOCK_SZ};
+use ext2::{Ext2FileSystem, FileType, Inode};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+const LONG_TARGET: &str =
+    "sub/deeper/../deeper/../deeper/../deeper/../deeper/../deeper/../deeper/file";
+
+fn big_data() -> Vec<u8> {
+    // past the doubly indirect blocks with 1 KiB blocks
+    (0..300 * 1024 + 123u32).map(|i| (i % 253) as u8).collect()
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ext2-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn populate(root: &Path) {
+    std::fs::write(root.join("hello.txt"), "hello ext2\n").unwrap();
+    std::fs::create_dir_all(root.join("sub/deeper")).unwrap();
+    std::fs::write(root.join("sub/deeper/file"), "deep\n").unwrap();
+    std::fs::create_dir(root.join("bin")).unwrap();
+    std::fs::write(root.join("bin/big.bin"), big_data()).unwrap();
+    let sparse = std::fs::File::create(root.join("sparse")).unwrap();
+    sparse.set_len(100 * 1024).unwrap();
+    std::fs::create_dir(root.join("many")).unwrap();
+    for i in 0..100 {
+        std::fs::write(root.join(format!("many/entry-{:03}", i)), format!("{}", i)).unwrap();
+    }
+    symlink("sub/deeper/file", root.join("link")).unwrap();
+    symlink("/hello.txt", root.join("abs")).unwrap();
+    symlink(LONG_TARGET, root.join("long-link")).unwrap();
+}
+
+/// `None` if mke2fs is not installed.
+fn mke2fs(name: &str, args: &[&str], tree: Option<&Path>) -> Option<Arc<dyn BlockDevice>> {
+    let dir = scratch_dir(name);
+    let image = dir.join("fs.img");
+    let mut command = Command::new("mke2fs");
+    command.args(["-q", "-F"]).args(args);
+    if let Some(tree) = tree {
+        command.arg("-d").arg(tree);
+    }
+    let status = command
+        .arg(&image)
+        .arg("4M")
+        .stdout(std::process::Stdio::null())
+        .status()
+        .ok()?;
+    assert!(status.success());
+    let bytes = std::fs::read(&image).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(bytes.len() / BLOCK_SZ));
+    for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+        device.write_block(i, block).unwrap();
+    }
+    Some(device)
+}
+
+fn read_all(inode: &Inode) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 777];
+    loop {
+        let len = inode.read_at(data.len(), &mut buf).unwrap();
+        if len == 0 {
+            return data;
+        }
+        data.extend_from_slice(&buf[..len]);
+    }
+}
+
+fn find(dir: &Inode, path: &str) -> Arc<Inode> {
+    let mut names = path.split('/');
+    let mut inode = dir.find(names.next().unwrap()).unwrap().unwrap();
+    for name in names {
+        inode = inode.find(name).unwrap().unwrap();
+    }
+    inode
+}
+
+fn check_tree(block_size: &str) {
+    let tree = scratch_dir(&format!("tree-{}", block_size));
+    populate(&tree);
+    let device = match mke2fs(block_size, &["-t", "ext2", "-b", block_size], Some(&tree)) {
+        Some(device) => device,
+        None => {
+            eprintln!("mke2fs not found, skipped");
+            return;
+        }
+    };
+    std::fs::remove_dir_all(&tree).unwrap();
+    let fs = Ext2FileSystem::open(device.clone()).unwrap();
+    assert_eq!(fs.sb.block_size.to_string(), block_size);
+    let root = Ext2FileSystem::root_inode(&fs);
+    let mut names = root.ls().unwrap();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "abs",
+            "bin",
+            "hello.txt",
+            "link",
+            "long-link",
+            "lost+found",
+            "many",
+            "sparse",
+            "sub"
+        ]
+    );
+    assert_eq!(read_all(&find(&root, "hello.txt")), b"hello ext2\n");
+    assert_eq!(read_all(&find(&root, "sub/deeper/file")), b"deep\n");
+    let big = find(&root, "bin/big.bin");
+    assert_eq!(big.size().unwrap(), big_data().len());
+    assert_eq!(read_all(&big), big_data());
+    let meta = big.metadata().unwrap();
+    assert_eq!(meta.mode & 0xf000, 0x8000);
+    assert_eq!(meta.nlink, 1);
+    assert!(meta.blocks as usize * 512 >= big_data().len());
+    assert!(meta.mtime > 0);
+    // "sub" holds "deeper", whose ".." links back
+    assert_eq!(find(&root, "sub").metadata().unwrap().nlink, 3);
+    // holes take no space
+    let sparse_inode = find(&root, "sparse");
+    assert!(sparse_inode.metadata().unwrap().blocks < 8);
+    let sparse = read_all(&sparse_inode);
+    assert_eq!(sparse.len(), 100 * 1024);
+    assert!(sparse.iter().all(|&b| b == 0));
+
+    let many = find(&root, "many");
+    assert_eq!(many.ls().unwrap().len(), 100);
+    assert_eq!(read_all(&find(&many, "entry-042")), b"42");
+    assert!(many.find("entry-100").unwrap().is_none());
+
+    let link = find(&root, "link");
+    assert_eq!(link.file_type().unwrap(), FileType::Symlink);
+    assert_eq!(link.read_link().unwrap().unwrap(), "sub/deeper/file");
+    assert_eq!(
+        find(&root, "abs").read_link().unwrap().unwrap(),
+        "/hello.txt"
+    );
+    // too long to be kept in the inode
+    assert_eq!(
+        find(&root, "long-link").read_link().unwrap().unwrap(),
+        LONG_TARGET
+    );
+    assert_eq!(find(&root, "hello.txt").read_link().unwrap(), None);
+    assert_eq!(
+        link.read_at(0, &mut [0u8; 4]).err(),
+        Some(FsError::InvalidArgument)
+    );
+
+    let hello = find(&root, "hello.txt");
+    assert_eq!(hello.find("x").err(), Some(FsError::NotDir));
+    assert_eq!(
+        root.read_at(0, &mut [0u8; 4]).err(),
+        Some(FsError::InvalidArgument)
+    );
+    drop((root, many, link, hello, fs));
+    Ext2FileSystem::unmount(&device).unwrap();
+}
+
+#[test]
+fn small_blocks() {
+    check_tree("1024");
+}
+
+#[test]
+fn large_blocks() {
+    check_tree("4096");
+}
+
+/// Overwrite the low 32 bits of the size of inode `inode_id` in group 0.
+fn set_inode_size(device: &Arc<dyn BlockDevice>, fs: &Ext2FileSystem, inode_id: u32, size: u32) {
+    let sb = &fs.sb;
+    assert!(inode_id <= sb.inodes_per_group);
+    let mut desc = [0u8; 4];
+    // the inode table of group 0, 8 bytes into its descriptor
+    fs.read_bytes(
+        8 + (sb.first_data_block as u64 + 1) * sb.block_size as u64,
+        &mut desc,
+    )
+    .unwrap();
+    let inode_table = u32::from_le_bytes(desc) as usize;
+    let pos = inode_table * sb.block_size + (inode_id as usize - 1) * sb.inode_size + 4;
+    let mut block = [0u8; BLOCK_SZ];
+    device.read_block(pos / BLOCK_SZ, &mut block).unwrap();
+    block[pos % BLOCK_SZ..pos % BLOCK_SZ + 4].copy_from_slice(&size.to_le_bytes());
+    device.write_block(pos / BLOCK_SZ, &block).unwrap();
+}
+
+#[test]
+fn corrupted_sizes() {
+    let tree = scratch_dir("tree-corrupted");
+    populate(&tree);
+    let device = match mke2fs("corrupted", &["-t", "ext2", "-b", "1024"], Some(&tree)) {
+        Some(device) => device,
+        None => return,
+    };
+    std::fs::remove_dir_all(&tree).unwrap();
+    let fs = Ext2FileSystem::open(device.clone()).unwrap();
+    let root = Ext2FileSystem::root_inode(&fs);
+    let many = find(&root, "many").inode_id();
+    let link = find(&root, "long-link").inode_id();
+    // write below the block cache, then read it again
+    drop(root);
+    Ext2FileSystem::unmount(&device).unwrap();
+    // larger than the whole filesystem, and than one block
+    set_inode_size(&device, &fs, many, u32::MAX);
+    set_inode_size(&device, &fs, link, 2 * 1024 * 1024);
+    drop(fs);
+
+    let fs = Ext2FileSystem::open(device.clone()).unwrap();
+    let root = Ext2FileSystem::root_inode(&fs);
+    assert_eq!(find(&root, "many").ls().err(), Some(FsError::Corrupted));
+    assert_eq!(
+        find(&root, "long-link").read_link().err(),
+        Some(FsError::Corrupted)
+    );
+    drop((root, fs));
+    Ext2FileSystem::unmount(&device).unwrap();
+}
+
+#[test]
+fn refuses_other_filesystems() {
+    let device = match mke2fs("ext4", &["-t", "ext4", "-O", "^has_journal"], None) {
+        Some(device) => device,
+        None => return,
+    };
+    // extents are an incompatible feature
+    assert_eq!(
+        Ext2FileSystem::open(device.clone()).err(),
+        Some(FsError::Unsupported)
+    );
+    Ext2FileSystem::unmount(&device).unwrap();
+    let blank: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(16));
+    assert_eq!(
+        Ext2FileSystem::open(blank.clone()).err(),
+        Some(FsError::Unsupported)
+    );
+    Ext2FileSystem::unmount(&blank).unwrap();
+}
@@ -0,0 +1,168 @@
+use super::{
+    read_u32, write_u32, BlockDevice, BootSector, FsError, FsResult, Inode, BLOCK_SZ, END_OF_CHAIN,
+    FAT_MASK,
+};
+use alloc::sync::Arc;
+use spin::Mutex;
+
+const FS_INFO_LEAD_SIG: u32 = 0x4161_5252;
+const FS_INFO_STRUC_SIG: u32 = 0x6141_7272;
+/// Free cluster count and next free hint in FSInfo.
+const FS_INFO_FREE_COUNT: usize = 488;
+const FS_INFO_NEXT_FREE: usize = 492;
+const UNKNOWN: u32 = 0xffff_ffff;
+
+pub struct FatFileSystem {
+    pub block_device: Arc<dyn BlockDevice>,
+    pub bs: BootSector,
+    /// Where the search for a free cluster starts.
+    next_free: u32,
+    /// FSInfo is only a hint, it gets marked unknown on the first change
+    /// instead of being kept up to date.
+    fs_info_stale: bool,
+}
+
+impl FatFileSystem {
+    /// Fail with `FsError::Unsupported` unless the device holds FAT32 with
+    /// 512 byte sectors.
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> FsResult<Arc<Mutex<Self>>> {
+        let mut buf = [0u8; BLOCK_SZ];
+        block_device.read_block(0, &mut buf)?;
+        let bs = BootSector::parse(&buf)?;
+        Ok(Arc::new(Mutex::new(Self {
+            block_device,
+            bs,
+            next_free: 2,
+            fs_info_stale: false,
+        })))
+    }
+    /// Every write goes straight to the device, so only its cache is left.
+    pub fn unmount(block_device: &Arc<dyn BlockDevice>) -> FsResult {
+        block_device.flush()
+    }
+    pub fn root_inode(fs: &Arc<Mutex<Self>>) -> Arc<Inode> {
+        Arc::new(Inode::new(None, fs.clone()))
+    }
+    pub fn read_sector(&self, sector: usize, buf: &mut [u8; BLOCK_SZ]) -> FsResult {
+        self.block_device.read_block(sector, buf)
+    }
+    pub fn write_sector(&self, sector: usize, buf: &[u8; BLOCK_SZ]) -> FsResult {
+        self.block_device.write_block(sector, buf)
+    }
+    pub fn cluster_size(&self) -> usize {
+        self.bs.sectors_per_cluster as usize * BLOCK_SZ
+    }
+    pub fn cluster_sector(&self, cluster: u32) -> usize {
+        (self.bs.data_start() + (cluster - 2) * self.bs.sectors_per_cluster) as usize
+    }
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.bs.cluster_count() + 2
+    }
+    fn fat_pos(&self, cluster: u32) -> (usize, usize) {
+        let offset = cluster as usize * 4;
+        (
+            self.bs.reserved_sectors as usize + offset / BLOCK_SZ,
+            offset % BLOCK_SZ,
+        )
+    }
+    fn fat_entry(&self, cluster: u32) -> FsResult<u32> {
+        let (sector, offset) = self.fat_pos(cluster);
+        let mut buf = [0u8; BLOCK_SZ];
+        self.read_sector(sector, &mut buf)?;
+        Ok(read_u32(&buf, offset) & FAT_MASK)
+    }
+    /// Update every copy of the FAT, keeping the reserved top bits.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> FsResult {
+        let (sector, offset) = self.fat_pos(cluster);
+        let mut buf = [0u8; BLOCK_SZ];
+        for fat in 0..self.bs.num_fats as usize {
+            let sector = sector + fat * self.bs.fat_size as usize;
+            self.read_sector(sector, &mut buf)?;
+            let old = read_u32(&buf, offset);
+            write_u32(&mut buf, offset, (old & !FAT_MASK) | value);
+            self.write_sector(sector, &buf)?;
+        }
+        Ok(())
+    }
+    /// `None` at the end of the chain.
+    pub fn next_cluster(&self, cluster: u32) -> FsResult<Option<u32>> {
+        let next = self.fat_entry(cluster)?;
+        if next >= END_OF_CHAIN {
+            Ok(None)
+        } else if self.is_valid_cluster(next) {
+            Ok(Some(next))
+        } else {
+            Err(FsError::Corrupted)
+        }
+    }
+    /// The first free cluster from `start` on, wrapping around to 2. Each FAT
+    /// sector is read once for all its entries.
+    fn find_free_cluster(&self, start: u32) -> FsResult<u32> {
+        let end = self.bs.cluster_count() + 2;
+        let mut buf = [0u8; BLOCK_SZ];
+        let mut loaded = None;
+        for cluster in (start..end).chain(2..start) {
+            let (sector, offset) = self.fat_pos(cluster);
+            if loaded != Some(sector) {
+                self.read_sector(sector, &mut buf)?;
+                loaded = Some(sector);
+            }
+            if read_u32(&buf, offset) & FAT_MASK == 0 {
+                return Ok(cluster);
+            }
+        }
+        Err(FsError::NoSpace)
+    }
+    /// Allocate a zeroed cluster ending a chain, and link it after `prev`.
+    pub fn alloc_cluster(&mut self, prev: Option<u32>) -> FsResult<u32> {
+        let start = if self.is_valid_cluster(self.next_free) {
+            self.next_free
+        } else {
+            2
+        };
+        let cluster = self.find_free_cluster(start)?;
+        self.mark_fs_info_stale()?;
+        let zero = [0u8; BLOCK_SZ];
+        let first = self.cluster_sector(cluster);
+        for sector in first..first + self.bs.sectors_per_cluster as usize {
+            self.write_sector(sector, &zero)?;
+        }
+        self.set_fat_entry(cluster, FAT_MASK)?;
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster)?;
+        }
+        self.next_free = cluster + 1;
+        Ok(cluster)
+    }
+    /// Free the chain starting at `first`.
+    pub fn free_chain(&mut self, first: u32) -> FsResult {
+        self.mark_fs_info_stale()?;
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+            let sector = self.cluster_sector(current);
+            self.block_device
+                .discard(sector..sector + self.bs.sectors_per_cluster as usize)?;
+        }
+        Ok(())
+    }
+    fn mark_fs_info_stale(&mut self) -> FsResult {
+        if self.fs_info_stale {
+            return Ok(());
+        }
+        self.fs_info_stale = true;
+        let sector = self.bs.fs_info_sector as usize;
+        if sector == 0 || sector >= self.bs.reserved_sectors as usize {
+            return Ok(());
+        }
+        let mut buf = [0u8; BLOCK_SZ];
+        self.read_sector(sector, &mut buf)?;
+        if read_u32(&buf, 0) != FS_INFO_LEAD_SIG || read_u32(&buf, 484) != FS_INFO_STRUC_SIG {
+            return Ok(());
+        }
+        write_u32(&mut buf, FS_INFO_FREE_COUNT, UNKNOWN);
+        write_u32(&mut buf, FS_INFO_NEXT_FREE, UNKNOWN);
+        self.write_sector(sector, &buf)
+    }
+}
@@ -0,0 +1,336 @@
+use super::{FsError, FsResult, BLOCK_SZ};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const DIRENT_SZ: usize = 32;
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+/// First name byte of a deleted entry.
+pub const DELETED: u8 = 0xe5;
+/// Set in the order byte of the last long name entry, which is stored first.
+pub const LAST_LONG_ENTRY: u8 = 0x40;
+/// UCS-2 characters in one long name entry.
+pub const LONG_NAME_CHARS: usize = 13;
+pub const MAX_NAME_LEN: usize = 255;
+/// FAT entries are 28 bits, values from this one on end a chain.
+pub const END_OF_CHAIN: u32 = 0x0fff_fff8;
+pub const FAT_MASK: u32 = 0x0fff_ffff;
+/// Lowercase flags in the reserved byte, as written by Windows and Linux.
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXT: u8 = 0x10;
+/// 1980-01-01, we have no clock.
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+pub fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+pub fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The fields of the boot sector we need, in sectors unless noted.
+#[derive(Clone, Copy, Debug)]
+pub struct BootSector {
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub num_fats: u32,
+    pub fat_size: u32,
+    pub total_sectors: u32,
+    pub root_cluster: u32,
+    pub fs_info_sector: u32,
+}
+
+impl BootSector {
+    pub fn parse(buf: &[u8; BLOCK_SZ]) -> FsResult<Self> {
+        if buf[510] != 0x55 || buf[511] != 0xaa {
+            return Err(FsError::Unsupported);
+        }
+        let bytes_per_sector = read_u16(buf, 11) as usize;
+        let root_entries = read_u16(buf, 17);
+        let fat_size16 = read_u16(buf, 22);
+        let total16 = read_u16(buf, 19);
+        // FAT12/16 have a fixed root directory and a 16 bit FAT size
+        if bytes_per_sector != BLOCK_SZ || root_entries != 0 || fat_size16 != 0 {
+            return Err(FsError::Unsupported);
+        }
+        let bs = Self {
+            sectors_per_cluster: buf[13] as u32,
+            reserved_sectors: read_u16(buf, 14) as u32,
+            num_fats: buf[16] as u32,
+            fat_size: read_u32(buf, 36),
+            total_sectors: if total16 != 0 {
+                total16 as u32
+            } else {
+                read_u32(buf, 32)
+            },
+            root_cluster: read_u32(buf, 44),
+            fs_info_sector: read_u16(buf, 48) as u32,
+        };
+        if !bs.sectors_per_cluster.is_power_of_two()
+            || bs.reserved_sectors == 0
+            || bs.num_fats == 0
+            || bs.data_start() >= bs.total_sectors
+            || bs.root_cluster < 2
+            || bs.root_cluster >= bs.cluster_count() + 2
+            || (bs.cluster_count() as u64 + 2) * 4 > bs.fat_size as u64 * BLOCK_SZ as u64
+        {
+            return Err(FsError::Corrupted);
+        }
+        Ok(bs)
+    }
+    pub fn data_start(&self) -> u32 {
+        self.reserved_sectors + self.num_fats * self.fat_size
+    }
+    pub fn cluster_count(&self) -> u32 {
+        (self.total_sectors - self.data_start()) / self.sectors_per_cluster
+    }
+}
+
+/// A short directory entry, the 8.3 name and the file's metadata.
+#[derive(Clone, Copy)]
+pub struct ShortEntry(pub [u8; DIRENT_SZ]);
+
+impl ShortEntry {
+    pub fn new(name: [u8; 11], case: u8, attr: u8, first_cluster: u32) -> Self {
+        let mut raw = [0u8; DIRENT_SZ];
+        raw[..11].copy_from_slice(&name);
+        raw[11] = attr;
+        raw[12] = case;
+        for offset in [16, 18, 24] {
+            write_u16(&mut raw, offset, DEFAULT_DATE);
+        }
+        let mut entry = Self(raw);
+        entry.set_first_cluster(first_cluster);
+        entry
+    }
+    pub fn attr(&self) -> u8 {
+        self.0[11]
+    }
+    pub fn is_dir(&self) -> bool {
+        self.attr() & ATTR_DIRECTORY != 0
+    }
+    pub fn first_cluster(&self) -> u32 {
+        ((read_u16(&self.0, 20) as u32) << 16) | read_u16(&self.0, 26) as u32
+    }
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        write_u16(&mut self.0, 20, (cluster >> 16) as u16);
+        write_u16(&mut self.0, 26, cluster as u16);
+    }
+    pub fn size(&self) -> u32 {
+        read_u32(&self.0, 28)
+    }
+    pub fn set_size(&mut self, size: u32) {
+        write_u32(&mut self.0, 28, size);
+    }
+    pub fn raw_name(&self) -> [u8; 11] {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&self.0[..11]);
+        name
+    }
+    /// "NAME.EXT", lowercased where the case flags say so.
+    pub fn name(&self) -> String {
+        let mut name = self.0[..11].to_vec();
+        // 0x05 stands for a real 0xe5
+        if name[0] == 0x05 {
+            name[0] = DELETED;
+        }
+        let case = self.0[12];
+        let part = |bytes: &[u8], lower: bool| -> String {
+            bytes
+                .iter()
+                .rev()
+                .skip_while(|&&b| b == b' ')
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .map(|&b| {
+                    let c = if b < 0x80 { b as char } else { '?' };
+                    if lower {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c
+                    }
+                })
+                .collect()
+        };
+        let mut s = part(&name[..8], case & LOWERCASE_BASE != 0);
+        let ext = part(&name[8..], case & LOWERCASE_EXT != 0);
+        if !ext.is_empty() {
+            s.push('.');
+            s.push_str(&ext);
+        }
+        s
+    }
+    pub fn is_dot(&self) -> bool {
+        &self.0[..11] == b".          " || &self.0[..11] == b"..         "
+    }
+}
+
+pub fn checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Character offsets of the 13 UCS-2 characters in a long name entry.
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The `index`-th (from 0) long name entry for `name`, out of `count`.
+pub fn long_entry(name: &[u16], index: usize, count: usize, checksum: u8) -> [u8; DIRENT_SZ] {
+    let mut raw = [0u8; DIRENT_SZ];
+    raw[0] = (index + 1) as u8;
+    if index + 1 == count {
+        raw[0] |= LAST_LONG_ENTRY;
+    }
+    raw[11] = ATTR_LONG_NAME;
+    raw[13] = checksum;
+    for (i, &offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+        let pos = index * LONG_NAME_CHARS + i;
+        // a NUL after the name, then padding
+        let c = match pos {
+            pos if pos < name.len() => name[pos],
+            pos if pos == name.len() => 0,
+            _ => 0xffff,
+        };
+        write_u16(&mut raw, offset, c);
+    }
+    raw
+}
+
+pub fn long_entry_chars(raw: &[u8; DIRENT_SZ]) -> [u16; LONG_NAME_CHARS] {
+    let mut chars = [0u16; LONG_NAME_CHARS];
+    for (c, &offset) in chars.iter_mut().zip(LONG_NAME_OFFSETS.iter()) {
+        *c = read_u16(raw, offset);
+    }
+    chars
+}
+
+/// Reject names Windows and Linux would not accept either.
+pub fn check_name(name: &str) -> FsResult {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.encode_utf16().count() > MAX_NAME_LEN
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || name
+            .chars()
+            .any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c))
+    {
+        return Err(FsError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn is_short_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || b"$%'-_@~`!(){}^#&".contains(&c)
+}
+
+/// The 8.3 name and case flags if `name` needs no long name entries.
+pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    let mut case = 0;
+    let (short_base, short_ext) = short.split_at_mut(8);
+    for (part, dest, flag) in [
+        (base, short_base, LOWERCASE_BASE),
+        (ext, short_ext, LOWERCASE_EXT),
+    ] {
+        let lower = part.bytes().any(|b| b.is_ascii_lowercase());
+        let upper = part.bytes().any(|b| b.is_ascii_uppercase());
+        // mixed case can only be kept in a long name
+        if lower && upper {
+            return None;
+        }
+        if lower {
+            case |= flag;
+        }
+        for (d, b) in dest.iter_mut().zip(part.bytes()) {
+            *d = b.to_ascii_uppercase();
+            if !is_short_char(*d) {
+                return None;
+            }
+        }
+    }
+    Some((short, case))
+}
+
+/// The "BASE~N.EXT" basis for a long name, `~N` is filled in later.
+pub fn short_name_basis(name: &str) -> ([u8; 6], [u8; 3]) {
+    let name = name.trim_start_matches('.');
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let convert = |part: &str, dest: &mut [u8]| {
+        let mut bytes = part.chars().filter(|&c| c != ' ' && c != '.').map(|c| {
+            let b = if c.is_ascii() {
+                c.to_ascii_uppercase() as u8
+            } else {
+                b'_'
+            };
+            if is_short_char(b) {
+                b
+            } else {
+                b'_'
+            }
+        });
+        for d in dest.iter_mut() {
+            *d = bytes.next().unwrap_or(b' ');
+        }
+    };
+    let mut base_part = [b' '; 6];
+    let mut ext_part = [b' '; 3];
+    convert(base, &mut base_part);
+    convert(ext, &mut ext_part);
+    if base_part[0] == b' ' {
+        base_part[0] = b'_';
+    }
+    (base_part, ext_part)
+}
+
+/// Put `~n` into the basis, shortening it as needed.
+pub fn numbered_short_name(basis: &([u8; 6], [u8; 3]), n: u32) -> [u8; 11] {
+    let mut tail = [0u8; 8];
+    let mut len = 0;
+    let mut digits = n;
+    while digits > 0 {
+        tail[7 - len] = b'0' + (digits % 10) as u8;
+        digits /= 10;
+        len += 1;
+    }
+    tail[7 - len] = b'~';
+    let tail = &tail[7 - len..];
+    let base_len = basis
+        .0
+        .iter()
+        .position(|&b| b == b' ')
+        .unwrap_or(6)
+        .min(8 - tail.len());
+    let mut short = [b' '; 11];
+    short[..base_len].copy_from_slice(&basis.0[..base_len]);
+    short[base_len..base_len + tail.len()].copy_from_slice(tail);
+    short[8..].copy_from_slice(&basis.1);
+    short
+}
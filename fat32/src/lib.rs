@@ -0,0 +1,15 @@
+//! FAT32 with long file names, on the block devices of easy-fs.
+
+#![no_std]
+
+extern crate alloc;
+
+mod fs;
+mod layout;
+mod vfs;
+
+pub use fs::FatFileSystem;
+pub use vfs::Inode;
+
+use easy_fs::{BlockDevice, FsError, FsResult, BLOCK_SZ};
+use layout::*;
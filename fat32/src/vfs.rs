@@ -0,0 +1,394 @@
+use super::{
+    check_name, checksum, exact_short_name, long_entry, long_entry_chars, numbered_short_name,
+    short_name_basis, FatFileSystem, FsError, FsResult, ShortEntry, ATTR_ARCHIVE, ATTR_DIRECTORY,
+    ATTR_LONG_NAME, ATTR_VOLUME_ID, BLOCK_SZ, DELETED, DIRENT_SZ, LAST_LONG_ENTRY, LONG_NAME_CHARS,
+};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Where a directory entry lives: sector and byte offset in it.
+type EntryPos = (usize, usize);
+
+/// Nothing is cached here, every operation reads the directory entry again,
+/// so any number of `Inode`s for one file stay consistent.
+pub struct Inode {
+    /// `None` for the root directory, which has no entry.
+    entry: Option<EntryPos>,
+    fs: Arc<Mutex<FatFileSystem>>,
+}
+
+struct DirEntry {
+    name: String,
+    pos: EntryPos,
+    short: ShortEntry,
+}
+
+impl Inode {
+    pub fn new(entry: Option<EntryPos>, fs: Arc<Mutex<FatFileSystem>>) -> Self {
+        Self { entry, fs }
+    }
+    /// The position of the directory entry, 0 for the root directory.
+    pub fn inode_id(&self) -> usize {
+        self.entry.map_or(0, |(sector, offset)| {
+            sector * (BLOCK_SZ / DIRENT_SZ) + offset / DIRENT_SZ
+        })
+    }
+    /// How many inodes of the filesystem are alive, this one included.
+    /// Other handles on the filesystem count too.
+    pub fn live_inodes(&self) -> usize {
+        Arc::strong_count(&self.fs)
+    }
+    fn read_short(&self, fs: &FatFileSystem) -> FsResult<Option<ShortEntry>> {
+        let (sector, offset) = match self.entry {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let mut buf = [0u8; BLOCK_SZ];
+        fs.read_sector(sector, &mut buf)?;
+        let mut raw = [0u8; DIRENT_SZ];
+        raw.copy_from_slice(&buf[offset..offset + DIRENT_SZ]);
+        Ok(Some(ShortEntry(raw)))
+    }
+    fn write_short(&self, fs: &FatFileSystem, short: &ShortEntry) -> FsResult {
+        let (sector, offset) = self.entry.ok_or(FsError::InvalidArgument)?;
+        write_raw(fs, (sector, offset), &short.0)
+    }
+    fn first_cluster(&self, fs: &FatFileSystem) -> FsResult<u32> {
+        Ok(match self.read_short(fs)? {
+            Some(short) => short.first_cluster(),
+            None => fs.bs.root_cluster,
+        })
+    }
+    fn is_dir_locked(&self, fs: &FatFileSystem) -> FsResult<bool> {
+        Ok(match self.read_short(fs)? {
+            Some(short) => short.is_dir(),
+            None => true,
+        })
+    }
+    pub fn is_dir(&self) -> FsResult<bool> {
+        self.is_dir_locked(&self.fs.lock())
+    }
+    /// Directories have no size in FAT, 0 is returned for them.
+    pub fn size(&self) -> FsResult<usize> {
+        let fs = self.fs.lock();
+        Ok(self
+            .read_short(&fs)?
+            .map_or(0, |short| short.size() as usize))
+    }
+    /// The clusters of the chain starting at `first`, none if it is 0.
+    fn clusters(fs: &FatFileSystem, first: u32) -> FsResult<Vec<u32>> {
+        let mut clusters = Vec::new();
+        let mut cluster = if first == 0 { None } else { Some(first) };
+        while let Some(current) = cluster {
+            // a loop in the chain
+            if clusters.len() > fs.bs.cluster_count() as usize {
+                return Err(FsError::Corrupted);
+            }
+            clusters.push(current);
+            cluster = fs.next_cluster(current)?;
+        }
+        Ok(clusters)
+    }
+    /// Every slot of this directory with its position.
+    fn slots(&self, fs: &FatFileSystem) -> FsResult<Vec<(EntryPos, [u8; DIRENT_SZ])>> {
+        if !self.is_dir_locked(fs)? {
+            return Err(FsError::NotDir);
+        }
+        let mut slots = Vec::new();
+        let mut buf = [0u8; BLOCK_SZ];
+        for cluster in Self::clusters(fs, self.first_cluster(fs)?)? {
+            let first = fs.cluster_sector(cluster);
+            for sector in first..first + fs.bs.sectors_per_cluster as usize {
+                fs.read_sector(sector, &mut buf)?;
+                for offset in (0..BLOCK_SZ).step_by(DIRENT_SZ) {
+                    let mut raw = [0u8; DIRENT_SZ];
+                    raw.copy_from_slice(&buf[offset..offset + DIRENT_SZ]);
+                    slots.push(((sector, offset), raw));
+                }
+            }
+        }
+        Ok(slots)
+    }
+    /// The files and directories in this directory, without "." and "..".
+    fn entries(&self, fs: &FatFileSystem) -> FsResult<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        // parts of the long name seen so far, last part first, and its checksum
+        let mut long_name: Vec<[u16; LONG_NAME_CHARS]> = Vec::new();
+        let mut long_checksum = 0;
+        let mut expected_order = 0;
+        for (pos, raw) in self.slots(fs)? {
+            if raw[0] == 0 {
+                break;
+            }
+            if raw[0] == DELETED {
+                long_name.clear();
+                continue;
+            }
+            if raw[11] & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                let order = raw[0] & !LAST_LONG_ENTRY;
+                if raw[0] & LAST_LONG_ENTRY != 0 {
+                    long_name.clear();
+                    long_checksum = raw[13];
+                } else if long_name.is_empty()
+                    || order != expected_order
+                    || raw[13] != long_checksum
+                {
+                    long_name.clear();
+                    continue;
+                }
+                long_name.push(long_entry_chars(&raw));
+                expected_order = order.wrapping_sub(1);
+                continue;
+            }
+            let short = ShortEntry(raw);
+            let long_name = core::mem::take(&mut long_name);
+            if short.attr() & ATTR_VOLUME_ID != 0 || short.is_dot() {
+                continue;
+            }
+            let name = if !long_name.is_empty()
+                && expected_order == 0
+                && long_checksum == checksum(&short.raw_name())
+            {
+                let chars: Vec<u16> = long_name
+                    .iter()
+                    .rev()
+                    .flat_map(|part| part.iter().copied())
+                    .take_while(|&c| c != 0)
+                    .collect();
+                char::decode_utf16(chars)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            } else {
+                short.name()
+            };
+            entries.push(DirEntry { name, pos, short });
+        }
+        Ok(entries)
+    }
+    pub fn find(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        let fs = self.fs.lock();
+        Ok(find_entry(&self.entries(&fs)?, name)
+            .map(|entry| Arc::new(Inode::new(Some(entry.pos), self.fs.clone()))))
+    }
+    pub fn ls(&self) -> FsResult<Vec<String>> {
+        let fs = self.fs.lock();
+        Ok(self
+            .entries(&fs)?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect())
+    }
+    /// Create a file, return `None` if `name` already exists.
+    pub fn create(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        self.create_entry(name, false)
+    }
+    /// Create a directory, return `None` if `name` already exists.
+    pub fn mkdir(&self, name: &str) -> FsResult<Option<Arc<Inode>>> {
+        self.create_entry(name, true)
+    }
+    fn create_entry(&self, name: &str, is_dir: bool) -> FsResult<Option<Arc<Inode>>> {
+        check_name(name)?;
+        let mut fs = self.fs.lock();
+        let entries = self.entries(&fs)?;
+        if find_entry(&entries, name).is_some() {
+            return Ok(None);
+        }
+        // short names are unique, including the ones behind long names
+        let (short_name, case, long_name) = match exact_short_name(name) {
+            Some((short_name, case))
+                if entries.iter().all(|e| e.short.raw_name() != short_name) =>
+            {
+                (short_name, case, Vec::new())
+            }
+            _ => {
+                let basis = short_name_basis(name);
+                let short_name = (1..)
+                    .map(|n| numbered_short_name(&basis, n))
+                    .find(|short_name| entries.iter().all(|e| e.short.raw_name() != *short_name))
+                    .unwrap();
+                (short_name, 0, name.encode_utf16().collect())
+            }
+        };
+        let long_count = match long_name.len() {
+            0 => 0,
+            len => (len - 1) / LONG_NAME_CHARS + 1,
+        };
+        let slots = self.free_slots(&mut fs, long_count + 1)?;
+        let (attr, first_cluster) = if is_dir {
+            (ATTR_DIRECTORY, self.init_dir(&mut fs)?)
+        } else {
+            (ATTR_ARCHIVE, 0)
+        };
+        let sum = checksum(&short_name);
+        for (i, &pos) in slots[..long_count].iter().enumerate() {
+            let index = long_count - 1 - i;
+            write_raw(&fs, pos, &long_entry(&long_name, index, long_count, sum))?;
+        }
+        let short = ShortEntry::new(short_name, case, attr, first_cluster);
+        write_raw(&fs, slots[long_count], &short.0)?;
+        Ok(Some(Arc::new(Inode::new(
+            Some(slots[long_count]),
+            self.fs.clone(),
+        ))))
+    }
+    /// A new directory cluster holding "." and "..".
+    fn init_dir(&self, fs: &mut FatFileSystem) -> FsResult<u32> {
+        let cluster = fs.alloc_cluster(None)?;
+        // ".." points at cluster 0 when the parent is the root
+        let parent = match self.entry {
+            Some(_) => self.first_cluster(fs)?,
+            None => 0,
+        };
+        let mut buf = [0u8; BLOCK_SZ];
+        buf[..DIRENT_SZ]
+            .copy_from_slice(&ShortEntry::new(*b".          ", 0, ATTR_DIRECTORY, cluster).0);
+        buf[DIRENT_SZ..2 * DIRENT_SZ]
+            .copy_from_slice(&ShortEntry::new(*b"..         ", 0, ATTR_DIRECTORY, parent).0);
+        let sector = fs.cluster_sector(cluster);
+        fs.write_sector(sector, &buf)?;
+        Ok(cluster)
+    }
+    /// `count` consecutive unused slots, growing the directory if needed.
+    fn free_slots(&self, fs: &mut FatFileSystem, count: usize) -> FsResult<Vec<EntryPos>> {
+        loop {
+            let slots = self.slots(fs)?;
+            let mut run = Vec::new();
+            for (pos, raw) in slots {
+                if raw[0] == 0 || raw[0] == DELETED {
+                    run.push(pos);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+            let last = *Self::clusters(fs, self.first_cluster(fs)?)?
+                .last()
+                .ok_or(FsError::Corrupted)?;
+            fs.alloc_cluster(Some(last))?;
+        }
+    }
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let fs = self.fs.lock();
+        let short = match self.read_short(&fs)? {
+            Some(short) if !short.is_dir() => short,
+            _ => return Err(FsError::InvalidArgument),
+        };
+        let end = (short.size() as usize).min(offset + buf.len());
+        if offset >= end {
+            return Ok(0);
+        }
+        let clusters = Self::clusters(&fs, short.first_cluster())?;
+        let mut sector_buf = [0u8; BLOCK_SZ];
+        let mut pos = offset;
+        while pos < end {
+            let sector = data_sector(&fs, &clusters, pos)?;
+            let sector_offset = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - sector_offset).min(end - pos);
+            fs.read_sector(sector, &mut sector_buf)?;
+            buf[pos - offset..pos - offset + len]
+                .copy_from_slice(&sector_buf[sector_offset..sector_offset + len]);
+            pos += len;
+        }
+        Ok(end - offset)
+    }
+    /// Files can not grow past 4 GiB.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        let mut fs = self.fs.lock();
+        self.write_locked(&mut fs, offset, buf)
+    }
+    /// Write at the end, under the lock so appends do not overwrite each
+    /// other. Return the offset `buf` went to.
+    pub fn append(&self, buf: &[u8]) -> FsResult<usize> {
+        let mut fs = self.fs.lock();
+        let offset = match self.read_short(&fs)? {
+            Some(short) if !short.is_dir() => short.size() as usize,
+            _ => return Err(FsError::InvalidArgument),
+        };
+        self.write_locked(&mut fs, offset, buf)?;
+        Ok(offset)
+    }
+    fn write_locked(&self, fs: &mut FatFileSystem, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        let mut short = match self.read_short(fs)? {
+            Some(short) if !short.is_dir() => short,
+            _ => return Err(FsError::InvalidArgument),
+        };
+        let end = offset + buf.len();
+        if end > u32::MAX as usize {
+            return Err(FsError::NoSpace);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let cluster_size = fs.cluster_size();
+        let mut clusters = Self::clusters(fs, short.first_cluster())?;
+        // new clusters are zeroed, so a hole past the old end reads as zeroes
+        while clusters.len() * cluster_size < end {
+            let cluster = fs.alloc_cluster(clusters.last().copied())?;
+            if clusters.is_empty() {
+                short.set_first_cluster(cluster);
+                self.write_short(fs, &short)?;
+            }
+            clusters.push(cluster);
+        }
+        let mut sector_buf = [0u8; BLOCK_SZ];
+        let mut pos = offset;
+        while pos < end {
+            let sector = data_sector(fs, &clusters, pos)?;
+            let sector_offset = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - sector_offset).min(end - pos);
+            if len < BLOCK_SZ {
+                fs.read_sector(sector, &mut sector_buf)?;
+            }
+            sector_buf[sector_offset..sector_offset + len]
+                .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            fs.write_sector(sector, &sector_buf)?;
+            pos += len;
+        }
+        if end > short.size() as usize {
+            short.set_size(end as u32);
+            self.write_short(fs, &short)?;
+        }
+        Ok(buf.len())
+    }
+    /// Truncate to zero length and free the clusters.
+    pub fn clear(&self) -> FsResult {
+        let mut fs = self.fs.lock();
+        let mut short = match self.read_short(&fs)? {
+            Some(short) if !short.is_dir() => short,
+            _ => return Err(FsError::InvalidArgument),
+        };
+        let first = short.first_cluster();
+        short.set_first_cluster(0);
+        short.set_size(0);
+        self.write_short(&fs, &short)?;
+        if first != 0 {
+            fs.free_chain(first)?;
+        }
+        Ok(())
+    }
+}
+
+/// Names are compared like Windows does, ignoring ASCII case.
+fn find_entry<'a>(entries: &'a [DirEntry], name: &str) -> Option<&'a DirEntry> {
+    entries
+        .iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
+
+fn data_sector(fs: &FatFileSystem, clusters: &[u32], pos: usize) -> FsResult<usize> {
+    let cluster = *clusters
+        .get(pos / fs.cluster_size())
+        .ok_or(FsError::Corrupted)?;
+    Ok(fs.cluster_sector(cluster) + pos % fs.cluster_size() / BLOCK_SZ)
+}
+
+fn write_raw(fs: &FatFileSystem, (sector, offset): EntryPos, raw: &[u8; DIRENT_SZ]) -> FsResult {
+    let mut buf = [0u8; BLOCK_SZ];
+    fs.read_sector(sector, &mut buf)?;
+    buf[offset..offset + DIRENT_SZ].copy_from_slice(raw);
+    fs.write_sector(sector, &buf)
+}
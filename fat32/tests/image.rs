@@ -0,0 +1,322 @@
+//! FAT32 images laid out like `mkfs.vfat -F 32`, and real ones when the
+//! host has dosfstools and mtools.
+
+use easy_fs::testing::RamDisk;
+use easy_fs::{BlockDevice, FsError, BLOCK_SZ};
+use fat32::FatFileSystem;
+use std::process::Command;
+use std::sync::Arc;
+
+const TOTAL_SECTORS: u32 = 65536;
+const RESERVED_SECTORS: u32 = 32;
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// An empty volume with the fields and defaults mkfs.vfat uses.
+fn mkfs(sectors_per_cluster: u32) -> Arc<dyn BlockDevice> {
+    let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(TOTAL_SECTORS as usize));
+    let clusters = (TOTAL_SECTORS - RESERVED_SECTORS) / sectors_per_cluster;
+    let fat_size = ((clusters + 2) * 4 - 1) / BLOCK_SZ as u32 + 1;
+    let mut boot = [0u8; BLOCK_SZ];
+    boot[..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+    boot[3..11].copy_from_slice(b"mkfs.fat");
+    put_u16(&mut boot, 11, BLOCK_SZ as u16);
+    boot[13] = sectors_per_cluster as u8;
+    put_u16(&mut boot, 14, RESERVED_SECTORS as u16);
+    boot[16] = 2;
+    boot[21] = 0xf8;
+    put_u16(&mut boot, 24, 32);
+    put_u16(&mut boot, 26, 64);
+    put_u32(&mut boot, 32, TOTAL_SECTORS);
+    put_u32(&mut boot, 36, fat_size);
+    put_u32(&mut boot, 44, 2);
+    put_u16(&mut boot, 48, 1);
+    put_u16(&mut boot, 50, 6);
+    boot[64] = 0x80;
+    boot[66] = 0x29;
+    boot[71..82].copy_from_slice(b"NO NAME    ");
+    boot[82..90].copy_from_slice(b"FAT32   ");
+    boot[510] = 0x55;
+    boot[511] = 0xaa;
+    device.write_block(0, &boot).unwrap();
+    device.write_block(6, &boot).unwrap();
+    let mut fs_info = [0u8; BLOCK_SZ];
+    put_u32(&mut fs_info, 0, 0x4161_5252);
+    put_u32(&mut fs_info, 484, 0x6141_7272);
+    put_u32(&mut fs_info, 488, clusters - 1);
+    put_u32(&mut fs_info, 492, 3);
+    put_u32(&mut fs_info, 508, 0xaa55_0000);
+    device.write_block(1, &fs_info).unwrap();
+    // media byte, reserved entry, and the root directory's single cluster
+    let mut fat = [0u8; BLOCK_SZ];
+    put_u32(&mut fat, 0, 0x0fff_fff8);
+    put_u32(&mut fat, 4, 0x0fff_ffff);
+    put_u32(&mut fat, 8, 0x0fff_ffff);
+    device.write_block(RESERVED_SECTORS as usize, &fat).unwrap();
+    device
+        .write_block((RESERVED_SECTORS + fat_size) as usize, &fat)
+        .unwrap();
+    device
+}
+
+fn read_all(inode: &fat32::Inode) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 700];
+    loop {
+        let len = inode.read_at(data.len(), &mut buf).unwrap();
+        if len == 0 {
+            return data;
+        }
+        data.extend_from_slice(&buf[..len]);
+    }
+}
+
+#[test]
+fn files_and_directories() {
+    let device = mkfs(1);
+    let fs = FatFileSystem::open(device.clone()).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    assert!(root.ls().unwrap().is_empty());
+
+    let hello = root.create("hello.txt").unwrap().unwrap();
+    assert_eq!(hello.write_at(0, b"hello, world!").unwrap(), 13);
+    assert!(root.create("HELLO.TXT").unwrap().is_none());
+    let found = root.find("Hello.Txt").unwrap().unwrap();
+    assert_eq!(found.inode_id(), hello.inode_id());
+    assert_eq!(read_all(&found), b"hello, world!");
+    assert_eq!(hello.find("x").err(), Some(FsError::NotDir));
+    assert_eq!(found.append(b"!!").unwrap(), 13);
+    assert_eq!(read_all(&hello), b"hello, world!!!");
+    assert_eq!(root.append(b"x").err(), Some(FsError::InvalidArgument));
+
+    // 16 entries per one-sector cluster, so the directory has to grow
+    let dir = root.mkdir("Directory With A Long Name").unwrap().unwrap();
+    assert!(dir.is_dir().unwrap());
+    let names: Vec<String> = (0..40)
+        .map(|i| format!("file number {} with a long name.data", i))
+        .collect();
+    for (i, name) in names.iter().enumerate() {
+        let file = dir.create(name).unwrap().unwrap();
+        file.write_at(0, name.as_bytes()).unwrap();
+        assert!(!file.is_dir().unwrap(), "{}", i);
+    }
+    let sub = dir.mkdir("sub").unwrap().unwrap();
+    sub.create("deep.bin").unwrap().unwrap();
+
+    // spans many clusters and leaves a hole
+    let big = root.create("big.bin").unwrap().unwrap();
+    let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+    big.write_at(3000, &data).unwrap();
+    let read = read_all(&big);
+    assert_eq!(read.len(), 13000);
+    assert_eq!(big.size().unwrap(), 13000);
+    assert!(read[..3000].iter().all(|&b| b == 0));
+    assert_eq!(&read[3000..], &data[..]);
+
+    // everything is on the device, open it again from scratch
+    drop((root, dir, sub, big, hello, found));
+    FatFileSystem::unmount(&device).unwrap();
+    let fs = FatFileSystem::open(device).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    assert_eq!(
+        root.ls().unwrap(),
+        vec!["hello.txt", "Directory With A Long Name", "big.bin"]
+    );
+    let dir = root.find("directory with a long name").unwrap().unwrap();
+    let mut listed = dir.ls().unwrap();
+    assert_eq!(listed.pop().unwrap(), "sub");
+    assert_eq!(listed, names);
+    for name in names.iter() {
+        assert_eq!(read_all(&dir.find(name).unwrap().unwrap()), name.as_bytes());
+    }
+    let sub = dir.find("SUB").unwrap().unwrap();
+    assert_eq!(sub.ls().unwrap(), vec!["deep.bin"]);
+
+    // freed clusters are used again
+    let big = root.find("big.bin").unwrap().unwrap();
+    big.clear().unwrap();
+    assert!(read_all(&big).is_empty());
+    let again = root.create("again.bin").unwrap().unwrap();
+    again.write_at(0, &data).unwrap();
+    assert_eq!(read_all(&again), data);
+}
+
+#[test]
+fn full_disk_wraps_around() {
+    let device = mkfs(8);
+    let fs = FatFileSystem::open(device).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    let cluster = vec![0x5a; 8 * BLOCK_SZ];
+    let first = root.create("first.bin").unwrap().unwrap();
+    first.write_at(0, &cluster).unwrap();
+    let fill = root.create("fill.bin").unwrap().unwrap();
+    let mut size = 0;
+    loop {
+        match fill.write_at(size, &cluster) {
+            Ok(len) => size += len,
+            Err(err) => {
+                assert_eq!(err, FsError::NoSpace);
+                break;
+            }
+        }
+    }
+
+    // the only free cluster is behind where the search starts
+    first.clear().unwrap();
+    assert_eq!(fill.write_at(size, &cluster).unwrap(), cluster.len());
+    assert_eq!(
+        fill.write_at(size + cluster.len(), &cluster).err(),
+        Some(FsError::NoSpace)
+    );
+    let mut buf = vec![0u8; cluster.len()];
+    assert_eq!(fill.read_at(size, &mut buf).unwrap(), cluster.len());
+    assert_eq!(buf, cluster);
+}
+
+#[test]
+fn short_names() {
+    let device = mkfs(8);
+    let fs = FatFileSystem::open(device.clone()).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    for name in [
+        "README",
+        "notes.txt",
+        "Mixed.Txt",
+        "longfilename1.txt",
+        "longfilename2.txt",
+    ] {
+        root.create(name).unwrap().unwrap();
+    }
+    assert_eq!(
+        root.ls().unwrap(),
+        vec![
+            "README",
+            "notes.txt",
+            "Mixed.Txt",
+            "longfilename1.txt",
+            "longfilename2.txt"
+        ]
+    );
+    // the root directory is the first cluster of the data area
+    let mut buf = [0u8; BLOCK_SZ];
+    device
+        .read_block(fs.lock().cluster_sector(2), &mut buf)
+        .unwrap();
+    let short_names: Vec<&[u8]> = buf
+        .chunks(32)
+        .filter(|entry| entry[0] != 0 && entry[11] != 0x0f)
+        .map(|entry| &entry[..11])
+        .collect();
+    assert_eq!(
+        short_names,
+        vec![
+            &b"README     "[..],
+            b"NOTES   TXT",
+            b"MIXED~1 TXT",
+            b"LONGFI~1TXT",
+            b"LONGFI~2TXT"
+        ]
+    );
+    // 4 short-only entries would leave the rest of the sector empty
+    assert_eq!(
+        buf.chunks(32).filter(|entry| entry[11] == 0x0f).count(),
+        1 + 2 + 2
+    );
+}
+
+#[test]
+fn bad_names_and_images() {
+    let device = mkfs(1);
+    let fs = FatFileSystem::open(device).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    for name in [
+        "",
+        ".",
+        "..",
+        "a:b",
+        "what?",
+        "dot.",
+        "a\\b",
+        &"x".repeat(256),
+    ] {
+        assert_eq!(
+            root.create(name).err(),
+            Some(FsError::InvalidArgument),
+            "{:?}",
+            name
+        );
+    }
+    let long = "x".repeat(255);
+    assert!(root.create(&long).unwrap().is_some());
+    assert_eq!(root.ls().unwrap(), vec![long]);
+
+    let blank: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+    assert_eq!(FatFileSystem::open(blank).err(), Some(FsError::Unsupported));
+}
+
+fn run(program: &str, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .unwrap_or_else(|err| panic!("can not run {}: {}", program, err));
+    assert!(output.status.success(), "{} {:?} failed", program, args);
+    output.stdout
+}
+
+/// Run with `cargo test -- --ignored` where mkfs.vfat, mtools and fsck.fat
+/// are installed.
+#[test]
+#[ignore]
+fn mkfs_vfat_interop() {
+    let dir = std::env::temp_dir().join(format!("fat32-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("fat.img");
+    let image = image.to_str().unwrap();
+    let _ = std::fs::remove_file(image);
+    run("mkfs.vfat", &["-F", "32", "-C", image, "33792"]);
+    let host_file = dir.join("host.txt");
+    std::fs::write(&host_file, "from the host\n".repeat(100)).unwrap();
+    run("mmd", &["-i", image, "::/Host Dir"]);
+    run(
+        "mcopy",
+        &[
+            "-i",
+            image,
+            host_file.to_str().unwrap(),
+            "::/Host Dir/A Long Host Name.txt",
+        ],
+    );
+
+    let bytes = std::fs::read(image).unwrap();
+    let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(bytes.len() / BLOCK_SZ));
+    for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+        device.write_block(i, block).unwrap();
+    }
+    let fs = FatFileSystem::open(device.clone()).unwrap();
+    let root = FatFileSystem::root_inode(&fs);
+    let host_dir = root.find("Host Dir").unwrap().unwrap();
+    assert_eq!(host_dir.ls().unwrap(), vec!["A Long Host Name.txt"]);
+    let file = host_dir.find("A Long Host Name.txt").unwrap().unwrap();
+    assert_eq!(read_all(&file), "from the host\n".repeat(100).as_bytes());
+
+    let ours = root.mkdir("Written Here").unwrap().unwrap();
+    let file = ours.create("Some Data.bin").unwrap().unwrap();
+    let data: Vec<u8> = (0..5000u32).map(|i| (i % 97) as u8 + b' ').collect();
+    file.write_at(0, &data).unwrap();
+
+    let mut bytes = vec![0u8; bytes.len()];
+    for (i, block) in bytes.chunks_mut(BLOCK_SZ).enumerate() {
+        device.read_block(i, block).unwrap();
+    }
+    std::fs::write(image, &bytes).unwrap();
+    let copied = run("mtype", &["-i", image, "::/Written Here/Some Data.bin"]);
+    assert_eq!(copied, data);
+    run("fsck.fat", &["-n", image]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -21,6 +21,16 @@ pub const MMIO: &[(usize, usize)] = &[
 
 pub type BlockDeviceImpl = crate::drivers::block::SDCardWrapper;
 
+use crate::drivers::block::BLOCK_DEVICE;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::BlockDevice;
+
+pub fn block_devices() -> Vec<(&'static str, Arc<dyn BlockDevice>)> {
+    vec![("mmcblk0", BLOCK_DEVICE.clone())]
+}
+
 pub fn device_init() {
     unimplemented!();
 }
@@ -3,6 +3,7 @@ pub const CLOCK_FREQ: usize = 12500000;
 pub const MMIO: &[(usize, usize)] = &[
     (0x1000_0000, 0x1000),
     (0x1000_1000, 0x1000),
+    (0x1000_2000, 0x1000),
     (0xC00_0000, 0x40_0000),
 ];
 
@@ -12,9 +13,28 @@ pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
 pub const VIRT_PLIC: usize = 0xC00_0000;
 pub const VIRT_UART: usize = 0x1000_0000;
 
-use crate::drivers::block::BLOCK_DEVICE;
+use crate::drivers::block::{VirtIOBlock, BLOCK_DEVICE, VIRTIO1};
 use crate::drivers::chardev::{CharDevice, UART};
 use crate::drivers::plic::{IntrTargetPriority, PLIC};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::BlockDevice;
+use lazy_static::*;
+
+lazy_static! {
+    /// An optional second disk, e.g. for scratch data or results.
+    pub static ref SECOND_BLOCK_DEVICE: Option<Arc<VirtIOBlock>> =
+        VirtIOBlock::probe(VIRTIO1).map(Arc::new);
+}
+
+pub fn block_devices() -> Vec<(&'static str, Arc<dyn BlockDevice>)> {
+    let mut devices = vec![("vda", BLOCK_DEVICE.clone())];
+    if let Some(device) = SECOND_BLOCK_DEVICE.as_ref() {
+        devices.push(("vdb", device.clone() as Arc<dyn BlockDevice>));
+    }
+    devices
+}
 
 pub fn device_init() {
     use riscv::register::sie;
@@ -24,7 +44,7 @@ pub fn device_init() {
     let machine = IntrTargetPriority::Machine;
     plic.set_threshold(hart_id, supervisor, 0);
     plic.set_threshold(hart_id, machine, 1);
-    for intr_src_id in [1usize, 10] {
+    for intr_src_id in [1usize, 2, 10] {
         plic.enable(hart_id, supervisor, intr_src_id);
         plic.set_priority(intr_src_id, 1);
     }
@@ -38,6 +58,11 @@ pub fn irq_handler() {
     let intr_src_id = plic.claim(0, IntrTargetPriority::Supervisor);
     match intr_src_id {
         1 => BLOCK_DEVICE.handle_irq(),
+        2 => {
+            if let Some(device) = SECOND_BLOCK_DEVICE.as_ref() {
+                device.handle_irq();
+            }
+        }
         10 => UART.handle_irq(),
         _ => panic!("unsupported IRQ {}", intr_src_id),
     }
@@ -2,15 +2,25 @@ mod sdcard;
 mod virtio_blk;
 
 pub use sdcard::SDCardWrapper;
-pub use virtio_blk::VirtIOBlock;
+pub use virtio_blk::{VirtIOBlock, VIRTIO1};
 
-use crate::board::BlockDeviceImpl;
+use crate::board::{block_devices, BlockDeviceImpl};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use easy_fs::BlockDevice;
 use lazy_static::*;
 
 lazy_static! {
     pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// All block devices by name, the boot disk comes first.
+    pub static ref BLOCK_DEVICES: Vec<(&'static str, Arc<dyn BlockDevice>)> = block_devices();
+}
+
+pub fn find_block_device(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES
+        .iter()
+        .find(|(device_name, _)| *device_name == name)
+        .map(|(_, device)| device.clone())
 }
 
 #[allow(unused)]
@@ -22,8 +32,10 @@ pub fn block_device_test() {
         for byte in write_buffer.iter_mut() {
             *byte = i as u8;
         }
-        block_device.write_block(i as usize, &write_buffer);
-        block_device.read_block(i as usize, &mut read_buffer);
+        block_device.write_block(i as usize, &write_buffer).unwrap();
+        block_device
+            .read_block(i as usize, &mut read_buffer)
+            .unwrap();
         assert_eq!(write_buffer, read_buffer);
     }
     println!("block device test passed!");
@@ -5,6 +5,7 @@
 use super::BlockDevice;
 use crate::sync::UPIntrFreeCell;
 use core::convert::TryInto;
+use easy_fs::{FsError, FsResult};
 use k210_hal::prelude::*;
 use k210_pac::{Peripherals, SPI0};
 use k210_soc::{
@@ -749,17 +750,17 @@ impl SDCardWrapper {
 }
 
 impl BlockDevice for SDCardWrapper {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult {
         self.0
             .exclusive_access()
             .read_sector(buf, block_id as u32)
-            .unwrap();
+            .map_err(|_| FsError::Io)
     }
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult {
         self.0
             .exclusive_access()
             .write_sector(buf, block_id as u32)
-            .unwrap();
+            .map_err(|_| FsError::Io)
     }
     fn handle_irq(&self) {
         unimplemented!();
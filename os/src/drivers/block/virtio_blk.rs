@@ -8,11 +8,17 @@ use crate::task::schedule;
 use crate::DEV_NON_BLOCKING_ACCESS;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use core::ops::Range;
+use easy_fs::{FsError, FsResult};
 use lazy_static::*;
 use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
 
-#[allow(unused)]
 const VIRTIO0: usize = 0x10001000;
+#[allow(unused)]
+pub const VIRTIO1: usize = 0x10002000;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_ID_BLOCK: u32 = 2;
 
 pub struct VirtIOBlock {
     virtio_blk: UPIntrFreeCell<VirtIOBlk<'static>>,
@@ -25,48 +31,63 @@ lazy_static! {
 }
 
 impl BlockDevice for VirtIOBlock {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> FsResult {
         let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
             let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.read_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
+            let task_cx_ptr = self
+                .virtio_blk
+                .exclusive_session(|blk| {
+                    let token = unsafe { blk.read_block_nb(block_id, buf, &mut resp) };
+                    token.map(|token| self.condvars.get(&token).unwrap().wait_no_sched())
+                })
+                .map_err(|_| FsError::Io)?;
             schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when reading VirtIOBlk"
-            );
+            if resp.status() != RespStatus::Ok {
+                return Err(FsError::Io);
+            }
+            Ok(())
         } else {
             self.virtio_blk
                 .exclusive_access()
                 .read_block(block_id, buf)
-                .expect("Error when reading VirtIOBlk");
+                .map_err(|_| FsError::Io)
         }
     }
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> FsResult {
         let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
             let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.write_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
+            let task_cx_ptr = self
+                .virtio_blk
+                .exclusive_session(|blk| {
+                    let token = unsafe { blk.write_block_nb(block_id, buf, &mut resp) };
+                    token.map(|token| self.condvars.get(&token).unwrap().wait_no_sched())
+                })
+                .map_err(|_| FsError::Io)?;
             schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when writing VirtIOBlk"
-            );
+            if resp.status() != RespStatus::Ok {
+                return Err(FsError::Io);
+            }
+            Ok(())
         } else {
             self.virtio_blk
                 .exclusive_access()
                 .write_block(block_id, buf)
-                .expect("Error when writing VirtIOBlk");
+                .map_err(|_| FsError::Io)
         }
     }
+    /// TODO: no VIRTIO_BLK_T_FLUSH is sent, the virtio-drivers version we use
+    /// can not issue one. It does not negotiate VIRTIO_BLK_F_FLUSH either,
+    /// which leaves the device write-through, so completed writes are durable.
+    fn flush(&self) -> FsResult {
+        Ok(())
+    }
+    /// TODO: no VIRTIO_BLK_T_DISCARD is sent, for the same reason. Discarding
+    /// is only a hint, freed blocks just keep their old content.
+    fn discard(&self, _blocks: Range<usize>) -> FsResult {
+        Ok(())
+    }
     fn handle_irq(&self) {
         self.virtio_blk.exclusive_session(|blk| {
             while let Ok(token) = blk.pop_used() {
@@ -78,8 +99,23 @@ impl BlockDevice for VirtIOBlock {
 
 impl VirtIOBlock {
     pub fn new() -> Self {
+        Self::probe(VIRTIO0).expect("no virtio block device")
+    }
+
+    /// Set up the device at `base`, return `None` if that slot is empty or
+    /// holds another kind of device.
+    pub fn probe(base: usize) -> Option<Self> {
+        let (magic, device_id) = unsafe {
+            (
+                (base as *const u32).read_volatile(),
+                ((base + 8) as *const u32).read_volatile(),
+            )
+        };
+        if magic != VIRTIO_MAGIC || device_id != VIRTIO_ID_BLOCK {
+            return None;
+        }
         let virtio_blk = unsafe {
-            UPIntrFreeCell::new(VirtIOBlk::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap())
+            UPIntrFreeCell::new(VirtIOBlk::new(&mut *(base as *mut VirtIOHeader)).unwrap())
         };
         let mut condvars = BTreeMap::new();
         let channels = virtio_blk.exclusive_access().virt_queue_size();
@@ -87,10 +123,10 @@ impl VirtIOBlock {
             let condvar = Condvar::new();
             condvars.insert(i, condvar);
         }
-        Self {
+        Some(Self {
             virtio_blk,
             condvars,
-        }
+        })
     }
 }
 
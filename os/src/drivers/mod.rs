@@ -2,5 +2,5 @@ pub mod block;
 pub mod chardev;
 pub mod plic;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{find_block_device, BLOCK_DEVICE};
 pub use chardev::UART;
@@ -0,0 +1,270 @@
+//! Character devices under /dev.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::{File, Stat, VfsResult};
+use crate::drivers::chardev::{CharDevice, UART};
+use crate::mm::UserBuffer;
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, FsError};
+
+pub struct DevFsType;
+
+impl FileSystemType for DevFsType {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        let tty: Arc<dyn File + Send + Sync> = Arc::new(Tty);
+        let random: Arc<dyn File + Send + Sync> = Arc::new(Random::new());
+        let devices: Vec<(&'static str, Arc<dyn File + Send + Sync>)> = vec![
+            ("null", Arc::new(Null)),
+            ("zero", Arc::new(Zero)),
+            ("tty", tty.clone()),
+            ("console", tty),
+            ("random", random.clone()),
+            ("urandom", random),
+        ];
+        let nodes = devices
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, file))| {
+                Arc::new(DevNode {
+                    id: i + 1,
+                    name,
+                    file,
+                })
+            })
+            .collect();
+        Ok(Arc::new(DevFs {
+            root: Arc::new(DevDir { nodes }),
+        }))
+    }
+}
+
+struct DevFs {
+    root: Arc<DevDir>,
+}
+
+impl SuperBlock for DevFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// The only directory, devices can not be added or removed.
+struct DevDir {
+    nodes: Vec<Arc<DevNode>>,
+}
+
+impl Inode for DevDir {
+    fn inode_id(&self) -> usize {
+        0
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(true)
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(0)
+    }
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(FsError::InvalidArgument.into())
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::InvalidArgument.into())
+    }
+    fn append(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::InvalidArgument.into())
+    }
+    fn clear(&self) -> VfsResult {
+        Err(FsError::InvalidArgument.into())
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(self
+            .nodes
+            .iter()
+            .find(|node| node.name == name)
+            .map(|node| node.clone() as Arc<dyn Inode>))
+    }
+    fn create(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::Unsupported.into())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        Ok(self
+            .nodes
+            .iter()
+            .map(|node| String::from(node.name))
+            .collect())
+    }
+}
+
+struct DevNode {
+    id: usize,
+    name: &'static str,
+    file: Arc<dyn File + Send + Sync>,
+}
+
+impl Inode for DevNode {
+    fn inode_id(&self) -> usize {
+        self.id
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(false)
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(0)
+    }
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(FsError::Unsupported.into())
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::Unsupported.into())
+    }
+    fn append(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::Unsupported.into())
+    }
+    /// Nothing to truncate, so `> /dev/null` works.
+    fn clear(&self) -> VfsResult {
+        Ok(())
+    }
+    fn find(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::NotDir.into())
+    }
+    fn create(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::NotDir.into())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        Err(FsError::NotDir.into())
+    }
+    fn device(&self) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(self.file.clone())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        let mut stat = self.file.stat()?;
+        stat.ino = self.id as u64;
+        Ok(stat)
+    }
+}
+
+/// Reads nothing, discards writes.
+struct Null;
+
+impl File for Null {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: UserBuffer) -> VfsResult<usize> {
+        Ok(0)
+    }
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(1, 3))
+    }
+}
+
+/// Reads zeroes, discards writes.
+struct Zero;
+
+impl File for Zero {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> VfsResult<usize> {
+        for slice in buf.buffers.iter_mut() {
+            slice.fill(0);
+        }
+        Ok(buf.len())
+    }
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(1, 5))
+    }
+}
+
+/// The UART, like stdin and stdout together.
+struct Tty;
+
+impl File for Tty {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    /// Block until one character arrives.
+    fn read(&self, mut buf: UserBuffer) -> VfsResult<usize> {
+        match buf.buffers.iter_mut().find(|slice| !slice.is_empty()) {
+            Some(slice) => {
+                slice[0] = UART.read();
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize> {
+        for slice in buf.buffers.iter() {
+            for &ch in slice.iter() {
+                UART.write(ch);
+            }
+        }
+        Ok(buf.len())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(5, 0))
+    }
+}
+
+/// xorshift64 seeded from the timer, not suitable for cryptography.
+/// Writes are accepted and ignored.
+struct Random {
+    state: UPIntrFreeCell<u64>,
+}
+
+impl Random {
+    fn new() -> Self {
+        Self {
+            state: unsafe { UPIntrFreeCell::new(get_time() as u64 | 1) },
+        }
+    }
+}
+
+impl File for Random {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> VfsResult<usize> {
+        let mut state = self.state.exclusive_access();
+        for slice in buf.buffers.iter_mut() {
+            for chunk in slice.chunks_mut(8) {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+            }
+        }
+        Ok(buf.len())
+    }
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(1, 8))
+    }
+}
@@ -0,0 +1,97 @@
+//! easy-fs as a VFS backend.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::{VfsError, VfsResult};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, EasyFileSystem, FsError, XattrMode};
+
+pub struct EasyFsType;
+
+impl FileSystemType for EasyFsType {
+    fn name(&self) -> &'static str {
+        "easy-fs"
+    }
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        let device = device.ok_or(FsError::InvalidArgument)?;
+        let efs = EasyFileSystem::open(device.clone())?;
+        Ok(Arc::new(EasyFsSuperBlock {
+            device,
+            root: EasyFileSystem::root_inode(&efs),
+        }))
+    }
+}
+
+/// The root inode keeps the whole filesystem alive.
+struct EasyFsSuperBlock {
+    device: Arc<dyn BlockDevice>,
+    root: Arc<easy_fs::Inode>,
+}
+
+impl SuperBlock for EasyFsSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn busy(&self) -> bool {
+        Arc::strong_count(&self.root) > 1 || self.root.live_inodes() > 1
+    }
+    fn unmount(&self) -> VfsResult {
+        Ok(EasyFileSystem::unmount(&self.device)?)
+    }
+}
+
+impl Inode for easy_fs::Inode {
+    fn inode_id(&self) -> usize {
+        easy_fs::Inode::inode_id(self) as usize
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(easy_fs::Inode::is_dir(self)?)
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(easy_fs::Inode::size(self)?)
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(easy_fs::Inode::read_at(self, offset, buf)?)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> VfsResult<usize> {
+        Ok(easy_fs::Inode::write_at(self, offset, buf)?)
+    }
+    fn append(&self, buf: &[u8]) -> VfsResult<usize> {
+        Ok(easy_fs::Inode::append(self, buf)?)
+    }
+    fn clear(&self) -> VfsResult {
+        Ok(easy_fs::Inode::clear(self)?)
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(easy_fs::Inode::find(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn create(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(easy_fs::Inode::create(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn mkdir(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(easy_fs::Inode::mkdir(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn unlink(&self, name: &str, dir: bool) -> VfsResult {
+        Ok(easy_fs::Inode::unlink(self, name, Some(dir))?)
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        Ok(easy_fs::Inode::ls(self)?)
+    }
+    fn get_xattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        Ok(easy_fs::Inode::get_xattr(self, name)?)
+    }
+    fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> VfsResult {
+        match easy_fs::Inode::set_xattr(self, name, value, mode)? {
+            true => Ok(()),
+            false if mode == XattrMode::Create => Err(VfsError::Exists),
+            false => Err(FsError::NotFound.into()),
+        }
+    }
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(easy_fs::Inode::list_xattr(self)?)
+    }
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        Ok(easy_fs::Inode::remove_xattr(self, name)?)
+    }
+}
@@ -0,0 +1,35 @@
+//! Errors of the VFS, which the filesystem libraries know nothing about.
+
+use core::fmt::{Display, Formatter, Result};
+use easy_fs::FsError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfsError {
+    /// From the filesystem below.
+    Fs(FsError),
+    /// Seeking a pipe or a terminal.
+    NotSeekable,
+    /// Creating a name which is taken.
+    Exists,
+    /// Still in use, e.g. a filesystem with mounts below it.
+    Busy,
+}
+
+pub type VfsResult<T = ()> = core::result::Result<T, VfsError>;
+
+impl From<FsError> for VfsError {
+    fn from(err: FsError) -> Self {
+        Self::Fs(err)
+    }
+}
+
+impl Display for VfsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Fs(err) => write!(f, "{}", err),
+            Self::NotSeekable => write!(f, "illegal seek"),
+            Self::Exists => write!(f, "already exists"),
+            Self::Busy => write!(f, "resource busy"),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Read-only ext2 as a VFS backend, for images made with `mke2fs -d`.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::{Stat, VfsResult};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, FsError};
+use ext2::Ext2FileSystem;
+
+pub struct Ext2FsType;
+
+impl FileSystemType for Ext2FsType {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        let device = device.ok_or(FsError::InvalidArgument)?;
+        let fs = Ext2FileSystem::open(device.clone())?;
+        Ok(Arc::new(Ext2SuperBlock {
+            device,
+            root: Ext2FileSystem::root_inode(&fs),
+        }))
+    }
+}
+
+struct Ext2SuperBlock {
+    device: Arc<dyn BlockDevice>,
+    root: Arc<ext2::Inode>,
+}
+
+impl SuperBlock for Ext2SuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn busy(&self) -> bool {
+        Arc::strong_count(&self.root) > 1 || self.root.live_inodes() > 1
+    }
+    fn unmount(&self) -> VfsResult {
+        Ok(Ext2FileSystem::unmount(&self.device)?)
+    }
+}
+
+impl Inode for ext2::Inode {
+    fn inode_id(&self) -> usize {
+        ext2::Inode::inode_id(self) as usize
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(ext2::Inode::is_dir(self)?)
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(ext2::Inode::size(self)?)
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(ext2::Inode::read_at(self, offset, buf)?)
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn append(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn clear(&self) -> VfsResult {
+        Err(FsError::ReadOnly.into())
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(ext2::Inode::find(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn create(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn mkdir(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        Ok(ext2::Inode::ls(self)?)
+    }
+    fn read_link(&self) -> VfsResult<Option<String>> {
+        Ok(ext2::Inode::read_link(self)?)
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        let meta = self.metadata()?;
+        let mut stat = Stat::new(self.inode_id() as usize, meta.mode as u32, self.size()?);
+        stat.nlink = meta.nlink as u32;
+        stat.uid = meta.uid as u32;
+        stat.gid = meta.gid as u32;
+        stat.blksize = meta.block_size as i32;
+        stat.blocks = meta.blocks as i64;
+        stat.atime = meta.atime as i64;
+        stat.mtime = meta.mtime as i64;
+        stat.ctime = meta.ctime as i64;
+        Ok(stat)
+    }
+}
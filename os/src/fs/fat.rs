@@ -0,0 +1,78 @@
+//! FAT32 as a VFS backend, for disks made with mkfs.vfat.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::VfsResult;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, FsError};
+use fat32::FatFileSystem;
+
+pub struct FatFsType;
+
+impl FileSystemType for FatFsType {
+    fn name(&self) -> &'static str {
+        "vfat"
+    }
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        let device = device.ok_or(FsError::InvalidArgument)?;
+        let fs = FatFileSystem::open(device.clone())?;
+        Ok(Arc::new(FatSuperBlock {
+            device,
+            root: FatFileSystem::root_inode(&fs),
+        }))
+    }
+}
+
+struct FatSuperBlock {
+    device: Arc<dyn BlockDevice>,
+    root: Arc<fat32::Inode>,
+}
+
+impl SuperBlock for FatSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn busy(&self) -> bool {
+        Arc::strong_count(&self.root) > 1 || self.root.live_inodes() > 1
+    }
+    fn unmount(&self) -> VfsResult {
+        Ok(FatFileSystem::unmount(&self.device)?)
+    }
+}
+
+impl Inode for fat32::Inode {
+    fn inode_id(&self) -> usize {
+        fat32::Inode::inode_id(self)
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(fat32::Inode::is_dir(self)?)
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(fat32::Inode::size(self)?)
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(fat32::Inode::read_at(self, offset, buf)?)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> VfsResult<usize> {
+        Ok(fat32::Inode::write_at(self, offset, buf)?)
+    }
+    fn append(&self, buf: &[u8]) -> VfsResult<usize> {
+        Ok(fat32::Inode::append(self, buf)?)
+    }
+    fn clear(&self) -> VfsResult {
+        Ok(fat32::Inode::clear(self)?)
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(fat32::Inode::find(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn create(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(fat32::Inode::create(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn mkdir(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Ok(fat32::Inode::mkdir(self, name)?.map(|inode| inode as Arc<dyn Inode>))
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        Ok(fat32::Inode::ls(self)?)
+    }
+}
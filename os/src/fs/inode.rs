@@ -1,41 +1,54 @@
-use super::File;
-use crate::drivers::BLOCK_DEVICE;
+use super::vfs::{lookup, lookup_nofollow, lookup_parent, DirEntry, Inode, WorkDir};
+use super::{File, SeekFrom, Stat, VfsError, VfsResult};
 use crate::mm::UserBuffer;
 use crate::sync::UPIntrFreeCell;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
-use lazy_static::*;
+use easy_fs::FsError;
 
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    /// Writes go to the end, whatever the offset.
+    append: bool,
+    /// Set for directories, to start paths from.
+    dir: Option<WorkDir>,
     inner: UPIntrFreeCell<OSInodeInner>,
 }
 
 pub struct OSInodeInner {
     offset: usize,
-    inode: Arc<Inode>,
+    inode: Arc<dyn Inode>,
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, inode: Arc<dyn Inode>) -> Self {
         Self {
             readable,
             writable,
+            append: false,
+            dir: None,
             inner: unsafe { UPIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
         }
     }
+    /// Directories are opened read-only.
+    fn new_dir(dir: WorkDir) -> Self {
+        let inode = dir.inode();
+        Self {
+            dir: Some(dir),
+            ..Self::new(true, false, inode)
+        }
+    }
     pub fn read_all(&self) -> Vec<u8> {
         let mut inner = self.inner.exclusive_access();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
-            if len == 0 {
-                break;
-            }
+            let len = match inner.inode.read_at(inner.offset, &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => len,
+            };
             inner.offset += len;
             v.extend_from_slice(&buffer[..len]);
         }
@@ -43,66 +56,148 @@ impl OSInode {
     }
 }
 
-lazy_static! {
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
-}
-
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in ROOT_INODE.ls() {
+    for app in WorkDir::root().unwrap().inode().ls().unwrap() {
         println!("{}", app);
     }
     println!("**************/")
 }
 
 bitflags! {
+    /// Numbered like on Linux.
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
-        const CREATE = 1 << 9;
-        const TRUNC = 1 << 10;
+        const CREATE = 1 << 6;
+        const EXCL = 1 << 7;
+        const TRUNC = 1 << 9;
+        const APPEND = 1 << 10;
+        const DIRECTORY = 1 << 16;
+        const CLOEXEC = 1 << 19;
     }
 }
 
 impl OpenFlags {
-    /// Do not check validity for simplicity
     /// Return (readable, writable)
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
-            (true, false)
-        } else if self.contains(Self::WRONLY) {
+        if self.contains(Self::WRONLY) {
             (false, true)
-        } else {
+        } else if self.contains(Self::RDWR) {
             (true, true)
+        } else {
+            (true, false)
         }
     }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
-        }
+/// Find an inode by path, e.g. for xattr syscalls.
+pub fn find_inode(cwd: &WorkDir, path: &str) -> VfsResult<Arc<dyn Inode>> {
+    lookup(cwd, path)
+}
+
+/// Like `find_inode`, but a symlink at the end of `path` is not followed.
+pub fn find_inode_nofollow(cwd: &WorkDir, path: &str) -> VfsResult<Arc<dyn Inode>> {
+    lookup_nofollow(cwd, path)
+}
+
+fn open_inode(cwd: &WorkDir, path: &str, flags: OpenFlags) -> VfsResult<Arc<dyn Inode>> {
+    // only regular files are created
+    if flags.contains(OpenFlags::CREATE | OpenFlags::DIRECTORY) {
+        return Err(FsError::InvalidArgument.into());
+    }
+    let exclusive = flags.contains(OpenFlags::CREATE | OpenFlags::EXCL);
+    // a symlink at the end exists for EXCL, even if its target does not
+    let found = if exclusive {
+        lookup_nofollow(cwd, path)
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
+        lookup(cwd, path)
+    };
+    let inode = match found {
+        Ok(_) if exclusive => return Err(VfsError::Exists),
+        Ok(inode) => inode,
+        Err(VfsError::Fs(FsError::NotFound)) if flags.contains(OpenFlags::CREATE) => {
+            let (dir, name) = lookup_parent(cwd, path)?;
+            match dir.create(name)? {
+                Some(inode) => return Ok(inode),
+                None if exclusive => return Err(VfsError::Exists),
+                // someone else has just created it, open theirs
+                None => lookup(cwd, path)?,
             }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        }
+        Err(err) => return Err(err),
+    };
+    let is_dir = inode.is_dir()?;
+    if flags.contains(OpenFlags::DIRECTORY) && !is_dir {
+        return Err(FsError::NotDir.into());
+    }
+    // directories are opened read-only, to list their entries
+    let (_, writable) = flags.read_write();
+    if is_dir && (writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC)) {
+        return Err(FsError::IsDir.into());
     }
+    if flags.contains(OpenFlags::TRUNC) {
+        // clear size
+        inode.clear()?;
+    }
+    Ok(inode)
+}
+
+/// Open a regular file, e.g. to load a program.
+pub fn open_file(cwd: &WorkDir, path: &str, flags: OpenFlags) -> VfsResult<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write();
+    let inode = open_inode(cwd, path, flags)?;
+    Ok(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// Open `path` for a file descriptor, device nodes give the device itself.
+pub fn open(cwd: &WorkDir, path: &str, flags: OpenFlags) -> VfsResult<Arc<dyn File + Send + Sync>> {
+    let inode = open_inode(cwd, path, flags)?;
+    if let Some(device) = inode.device() {
+        return Ok(device);
+    }
+    if inode.is_dir()? {
+        return Ok(Arc::new(OSInode::new_dir(cwd.chdir(path)?)));
+    }
+    let (readable, writable) = flags.read_write();
+    Ok(Arc::new(OSInode {
+        append: flags.contains(OpenFlags::APPEND),
+        ..OSInode::new(readable, writable, inode)
+    }))
+}
+
+/// Read into `buf` from `offset` on, return how much was read.
+fn read_inode(inode: &Arc<dyn Inode>, offset: usize, mut buf: UserBuffer) -> VfsResult<usize> {
+    if inode.is_dir()? {
+        return Err(FsError::IsDir.into());
+    }
+    let mut total_read_size = 0usize;
+    for slice in buf.buffers.iter_mut() {
+        // an error ends the read early, it is reported if nothing was read
+        let read_size = match inode.read_at(offset + total_read_size, *slice) {
+            Ok(0) => break,
+            Ok(read_size) => read_size,
+            Err(err) if total_read_size == 0 => return Err(err),
+            Err(_) => break,
+        };
+        total_read_size += read_size;
+    }
+    Ok(total_read_size)
+}
+
+fn write_inode(inode: &Arc<dyn Inode>, offset: usize, buf: UserBuffer) -> VfsResult<usize> {
+    let mut total_write_size = 0usize;
+    for slice in buf.buffers.iter() {
+        let write_size = match inode.write_at(offset + total_write_size, *slice) {
+            Ok(write_size) => write_size,
+            Err(err) if total_write_size == 0 => return Err(err),
+            Err(_) => break,
+        };
+        assert_eq!(write_size, slice.len());
+        total_write_size += write_size;
+    }
+    Ok(total_write_size)
 }
 
 impl File for OSInode {
@@ -112,28 +207,69 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, buf: UserBuffer) -> VfsResult<usize> {
         let mut inner = self.inner.exclusive_access();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
+        let read_size = read_inode(&inner.inode, inner.offset, buf)?;
+        inner.offset += read_size;
+        Ok(read_size)
+    }
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize> {
+        let mut inner = self.inner.exclusive_access();
+        if self.append {
+            // in one piece, so the end can not move while writing
+            let data: Vec<u8> = buf.buffers.concat();
+            inner.offset = inner.inode.append(&data)? + data.len();
+            return Ok(data.len());
         }
-        total_read_size
+        let write_size = write_inode(&inner.inode, inner.offset, buf)?;
+        inner.offset += write_size;
+        Ok(write_size)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    /// Seeking past the end is allowed, a later write leaves a hole.
+    fn seek(&self, pos: SeekFrom) -> VfsResult<usize> {
         let mut inner = self.inner.exclusive_access();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (0, offset as isize),
+            SeekFrom::Current(delta) => (inner.offset, delta),
+            SeekFrom::End(delta) => (inner.inode.size()?, delta),
+        };
+        let offset = (base as isize)
+            .checked_add(delta)
+            .filter(|offset| *offset >= 0)
+            .ok_or(FsError::InvalidArgument)?;
+        inner.offset = offset as usize;
+        Ok(inner.offset)
+    }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> VfsResult<usize> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        read_inode(&inode, offset, buf)
+    }
+    /// Writes at `offset` even with `APPEND`, as POSIX asks.
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> VfsResult<usize> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        write_inode(&inode, offset, buf)
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        self.inner.exclusive_access().inode.stat()
+    }
+    fn work_dir(&self) -> Option<WorkDir> {
+        self.dir.clone()
+    }
+    /// The offset of a directory counts entries.
+    fn read_dir(&self, emit: &mut dyn FnMut(usize, &DirEntry) -> bool) -> VfsResult<usize> {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.inode.is_dir()? {
+            return Err(FsError::NotDir.into());
+        }
+        let entries = inner.inode.dirents()?;
+        let mut taken = 0;
+        for entry in entries.iter().skip(inner.offset) {
+            if !emit(inner.offset + 1, entry) {
+                break;
+            }
+            inner.offset += 1;
+            taken += 1;
         }
-        total_write_size
+        Ok(taken)
     }
 }
@@ -1,16 +1,93 @@
+mod devfs;
+mod easyfs;
+mod error;
+mod ext2;
+mod fat;
 mod inode;
 mod pipe;
+mod procfs;
+mod stat;
 mod stdio;
+mod tmpfs;
+mod vfs;
 
+use crate::drivers::BLOCK_DEVICE;
 use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+use easy_fs::FsError;
+
+/// Where `File::seek` counts from.
+#[derive(Clone, Copy)]
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
-    fn read(&self, buf: UserBuffer) -> usize;
-    fn write(&self, buf: UserBuffer) -> usize;
+    /// Return the number of bytes read, or the error if nothing could be read.
+    fn read(&self, buf: UserBuffer) -> VfsResult<usize>;
+    fn write(&self, buf: UserBuffer) -> VfsResult<usize>;
+    /// Move the offset and return it. Pipes and terminals can not seek.
+    fn seek(&self, _pos: SeekFrom) -> VfsResult<usize> {
+        Err(VfsError::NotSeekable)
+    }
+    /// Like `read`, at `offset` and without moving the offset.
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> VfsResult<usize> {
+        Err(VfsError::NotSeekable)
+    }
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> VfsResult<usize> {
+        Err(VfsError::NotSeekable)
+    }
+    fn stat(&self) -> VfsResult<Stat>;
+    /// Hand directory entries from the offset on to `emit`, with the offset
+    /// just past each, until it returns false. The offset moves past the
+    /// entries taken, their number is returned.
+    fn read_dir(&self, _emit: &mut dyn FnMut(usize, &DirEntry) -> bool) -> VfsResult<usize> {
+        Err(FsError::NotDir.into())
+    }
+    /// The directory it was opened as, for `fchdir`.
+    fn work_dir(&self) -> Option<WorkDir> {
+        None
+    }
 }
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags};
+pub use error::{VfsError, VfsResult};
+pub use inode::{find_inode, find_inode_nofollow, list_apps, open, open_file, OSInode, OpenFlags};
 pub use pipe::{make_pipe, Pipe};
+pub use stat::*;
 pub use stdio::{Stdin, Stdout};
+pub use vfs::{lookup_parent, mount, umount, unlink, DirEntry, WorkDir};
+
+/// Tried on the boot disk in this order.
+const ROOT_FS_TYPES: [&str; 2] = ["easy-fs", "ext2"];
+
+/// Register the filesystem types, mount the root filesystem, devfs, procfs
+/// and a tmpfs at /tmp. Falls back to a tmpfs root if the disk can not be mounted.
+pub fn init() {
+    vfs::register_filesystem(Arc::new(easyfs::EasyFsType));
+    vfs::register_filesystem(Arc::new(ext2::Ext2FsType));
+    vfs::register_filesystem(Arc::new(fat::FatFsType));
+    vfs::register_filesystem(Arc::new(devfs::DevFsType));
+    vfs::register_filesystem(Arc::new(procfs::ProcFsType));
+    vfs::register_filesystem(Arc::new(tmpfs::TmpFsType));
+    let root_mounted = ROOT_FS_TYPES
+        .iter()
+        .any(|fs_type| mount("/", fs_type, Some(BLOCK_DEVICE.clone())).is_ok());
+    if !root_mounted {
+        println!("[kernel] can not mount the root disk, using tmpfs");
+        mount("/", "tmpfs", None).unwrap();
+        let root = WorkDir::root().unwrap().inode();
+        for dir in ["dev", "proc", "tmp"] {
+            root.mkdir(dir).unwrap();
+        }
+    }
+    // a read-only root may lack the mount points
+    for (path, fs_type) in [("/dev", "devfs"), ("/proc", "procfs"), ("/tmp", "tmpfs")] {
+        if let Err(err) = mount(path, fs_type, None) {
+            println!("[kernel] can not mount {} on {}: {}", fs_type, path, err);
+        }
+    }
+}
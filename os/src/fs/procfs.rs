@@ -0,0 +1,240 @@
+//! Kernel state as read-only text files under /proc.
+
+use super::vfs::{mounts, FileSystemType, Inode, SuperBlock};
+use super::VfsResult;
+use crate::config::{CLOCK_FREQ, PAGE_SIZE};
+use crate::mm::{frame_stats, heap_stats, MapPermission};
+use crate::task::{pid2process, pids, TaskStatus};
+use crate::timer::get_time_ms;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use easy_fs::{BlockDevice, FsError};
+
+pub struct ProcFsType;
+
+impl FileSystemType for ProcFsType {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        Ok(Arc::new(ProcFs))
+    }
+}
+
+struct ProcFs;
+
+impl SuperBlock for ProcFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(ProcInode(Node::Root))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Node {
+    Root,
+    MemInfo,
+    Uptime,
+    Mounts,
+    Process(usize),
+    Status(usize),
+    Cmdline(usize),
+}
+
+const ROOT_FILES: [(&str, Node); 3] = [
+    ("meminfo", Node::MemInfo),
+    ("uptime", Node::Uptime),
+    ("mounts", Node::Mounts),
+];
+
+const PROCESS_FILES: [&str; 2] = ["status", "cmdline"];
+
+/// Contents are generated again on every read.
+struct ProcInode(Node);
+
+impl ProcInode {
+    /// `None` once the process has exited.
+    fn content(&self) -> Option<String> {
+        match self.0 {
+            Node::MemInfo => Some(meminfo()),
+            Node::Uptime => {
+                let ms = get_time_ms();
+                Some(format!("{}.{:02}\n", ms / 1000, ms % 1000 / 10))
+            }
+            Node::Mounts => Some(
+                mounts()
+                    .into_iter()
+                    .map(|(path, fs_type)| format!("{} {}\n", fs_type, path))
+                    .collect(),
+            ),
+            Node::Status(pid) => status(pid),
+            Node::Cmdline(pid) => {
+                let process = pid2process(pid)?;
+                let inner = process.inner_exclusive_access();
+                let mut cmdline = String::new();
+                for arg in inner.cmdline.iter() {
+                    cmdline.push_str(arg);
+                    cmdline.push('\0');
+                }
+                Some(cmdline)
+            }
+            Node::Root | Node::Process(_) => None,
+        }
+    }
+}
+
+fn meminfo() -> String {
+    let (free_frames, total_frames) = frame_stats();
+    let (heap_used, heap_total) = heap_stats();
+    format!(
+        "MemTotal:\t{} kB\nMemFree:\t{} kB\nHeapTotal:\t{} kB\nHeapUsed:\t{} kB\n",
+        total_frames * PAGE_SIZE / 1024,
+        free_frames * PAGE_SIZE / 1024,
+        heap_total / 1024,
+        heap_used / 1024,
+    )
+}
+
+/// One "Key:\tvalue" per line, "Thread" and "Area" lines repeat.
+fn status(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    let threads: Vec<(usize, TaskStatus, usize)> = inner
+        .tasks
+        .iter()
+        .enumerate()
+        .filter_map(|(tid, task)| {
+            let task = task.as_ref()?.inner_exclusive_access();
+            Some((tid, task.task_status, task.run_time))
+        })
+        .collect();
+    let state = if inner.is_zombie {
+        "Zombie"
+    } else if threads.iter().any(|t| t.1 == TaskStatus::Running) {
+        "Running"
+    } else if threads.iter().any(|t| t.1 == TaskStatus::Ready) {
+        "Ready"
+    } else {
+        "Blocking"
+    };
+    let areas = inner.memory_set.areas();
+    let vm_size: usize = areas.iter().map(|(start, end, _)| end.0 - start.0).sum();
+    let run_time: usize = threads.iter().map(|t| t.2).sum();
+    let mut s = String::new();
+    let name = inner.cmdline.first().map_or("", String::as_str);
+    writeln!(s, "Name:\t{}", name).unwrap();
+    writeln!(s, "Pid:\t{}", pid).unwrap();
+    writeln!(s, "PPid:\t{}", ppid).unwrap();
+    writeln!(s, "State:\t{}", state).unwrap();
+    writeln!(s, "Threads:\t{}", threads.len()).unwrap();
+    let fds = inner.fd_table.iter().filter(|fd| fd.is_some()).count();
+    writeln!(s, "Fds:\t{}", fds).unwrap();
+    writeln!(s, "RunTimeMs:\t{}", ticks_to_ms(run_time)).unwrap();
+    writeln!(s, "VmSize:\t{} kB", vm_size / 1024).unwrap();
+    for (tid, status, run_time) in threads {
+        let run_time = ticks_to_ms(run_time);
+        writeln!(s, "Thread:\t{} {:?} {} ms", tid, status, run_time).unwrap();
+    }
+    for (start, end, perm) in areas {
+        let mut flags = String::new();
+        for (flag, ch) in [
+            (MapPermission::R, 'r'),
+            (MapPermission::W, 'w'),
+            (MapPermission::X, 'x'),
+        ] {
+            flags.push(if perm.contains(flag) { ch } else { '-' });
+        }
+        writeln!(s, "Area:\t{:#x}-{:#x} {}", start.0, end.0, flags).unwrap();
+    }
+    Some(s)
+}
+
+fn ticks_to_ms(ticks: usize) -> usize {
+    ticks / (CLOCK_FREQ / 1000)
+}
+
+impl Inode for ProcInode {
+    fn inode_id(&self) -> usize {
+        match self.0 {
+            Node::Root => 1,
+            Node::MemInfo => 2,
+            Node::Uptime => 3,
+            Node::Mounts => 4,
+            Node::Process(pid) => (pid + 1) << 4,
+            Node::Status(pid) => ((pid + 1) << 4) + 1,
+            Node::Cmdline(pid) => ((pid + 1) << 4) + 2,
+        }
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(matches!(self.0, Node::Root | Node::Process(_)))
+    }
+    /// Generated to measure it, like reading the whole file.
+    fn size(&self) -> VfsResult<usize> {
+        Ok(self.content().map_or(0, |content| content.len()))
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.is_dir()? {
+            return Err(FsError::InvalidArgument.into());
+        }
+        let content = self.content().ok_or(FsError::NotFound)?;
+        let content = content.as_bytes().get(offset..).unwrap_or(&[]);
+        let len = content.len().min(buf.len());
+        buf[..len].copy_from_slice(&content[..len]);
+        Ok(len)
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn append(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(FsError::ReadOnly.into())
+    }
+    fn clear(&self) -> VfsResult {
+        Err(FsError::ReadOnly.into())
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        let node = match self.0 {
+            Node::Root => ROOT_FILES
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, node)| *node)
+                .or_else(|| {
+                    let pid = name.parse().ok()?;
+                    pid2process(pid).map(|_| Node::Process(pid))
+                }),
+            Node::Process(pid) => match name {
+                "status" => Some(Node::Status(pid)),
+                "cmdline" => Some(Node::Cmdline(pid)),
+                _ => None,
+            },
+            _ => return Err(FsError::NotDir.into()),
+        };
+        Ok(node.map(|node| Arc::new(ProcInode(node)) as Arc<dyn Inode>))
+    }
+    fn create(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        if !self.is_dir()? {
+            return Err(FsError::NotDir.into());
+        }
+        Err(FsError::ReadOnly.into())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        match self.0 {
+            Node::Root => {
+                let mut names: Vec<String> = ROOT_FILES
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                names.extend(pids().into_iter().map(|pid| pid.to_string()));
+                Ok(names)
+            }
+            Node::Process(_) => Ok(PROCESS_FILES.iter().map(|name| name.to_string()).collect()),
+            _ => Err(FsError::NotDir.into()),
+        }
+    }
+}
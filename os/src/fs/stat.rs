@@ -0,0 +1,66 @@
+//! `struct stat` as laid out by Linux on riscv64, so it stays stable.
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// Fields a filesystem can not tell are 0, times are seconds since the epoch.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    /// File type and permission bits.
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    /// In 512 byte units.
+    pub blocks: i64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+const _: () = assert!(core::mem::size_of::<Stat>() == 128);
+
+impl Stat {
+    pub fn new(ino: usize, mode: u32, size: usize) -> Self {
+        Self {
+            ino: ino as u64,
+            mode,
+            nlink: 1,
+            size: size as i64,
+            blksize: 512,
+            blocks: ((size + 511) / 512) as i64,
+            ..Self::default()
+        }
+    }
+    /// Numbered like on Linux, e.g. 1:3 for /dev/null.
+    pub fn char_device(major: u32, minor: u32) -> Self {
+        Self {
+            rdev: ((major << 8) | minor) as u64,
+            ..Self::new(0, S_IFCHR | 0o666, 0)
+        }
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::File;
+use super::{File, Stat, VfsResult};
 use crate::drivers::chardev::{CharDevice, UART};
 use crate::mm::UserBuffer;
 
@@ -12,18 +12,22 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
+    fn read(&self, mut user_buf: UserBuffer) -> VfsResult<usize> {
         assert_eq!(user_buf.len(), 1);
         //println!("before UART.read() in Stdin::read()");
         let ch = UART.read();
         unsafe {
             user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
         }
-        1
+        Ok(1)
     }
-    fn write(&self, _user_buf: UserBuffer) -> usize {
+    fn write(&self, _user_buf: UserBuffer) -> VfsResult<usize> {
         panic!("Cannot write to stdin!");
     }
+    /// The console.
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(5, 1))
+    }
 }
 
 impl File for Stdout {
@@ -33,13 +37,16 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _user_buf: UserBuffer) -> usize {
+    fn read(&self, _user_buf: UserBuffer) -> VfsResult<usize> {
         panic!("Cannot read from stdout!");
     }
-    fn write(&self, user_buf: UserBuffer) -> usize {
+    fn write(&self, user_buf: UserBuffer) -> VfsResult<usize> {
         for buffer in user_buf.buffers.iter() {
             print!("{}", core::str::from_utf8(*buffer).unwrap());
         }
-        user_buf.len()
+        Ok(user_buf.len())
+    }
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat::char_device(5, 1))
     }
 }
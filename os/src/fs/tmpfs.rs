@@ -0,0 +1,206 @@
+//! Files kept in kernel frames, gone once unmounted.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::VfsResult;
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::{BlockDevice, FsError};
+
+pub struct TmpFsType;
+
+impl FileSystemType for TmpFsType {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+    fn mount(&self, _device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>> {
+        Ok(Arc::new(TmpFs {
+            root: TmpInode::new(Content::Dir(BTreeMap::new())),
+        }))
+    }
+}
+
+struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+impl SuperBlock for TmpFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// A quarter of the memory, so one file can not take all the frames.
+const MAX_FILE_SIZE: usize = 0x20_0000;
+
+/// Shared by all tmpfs instances, so ids are unique within each of them.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+enum Content {
+    /// Bytes past `size` are always zero.
+    File {
+        frames: Vec<FrameTracker>,
+        size: usize,
+    },
+    Dir(BTreeMap<String, Arc<TmpInode>>),
+}
+
+struct TmpInode {
+    id: usize,
+    content: UPIntrFreeCell<Content>,
+}
+
+impl TmpInode {
+    fn new(content: Content) -> Arc<Self> {
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            content: unsafe { UPIntrFreeCell::new(content) },
+        })
+    }
+    fn add(&self, name: &str, content: Content) -> VfsResult<Option<Arc<dyn Inode>>> {
+        let mut dir = self.content.exclusive_access();
+        let children = match &mut *dir {
+            Content::Dir(children) => children,
+            Content::File { .. } => return Err(FsError::NotDir.into()),
+        };
+        if children.contains_key(name) {
+            return Ok(None);
+        }
+        let inode = TmpInode::new(content);
+        children.insert(String::from(name), inode.clone());
+        Ok(Some(inode))
+    }
+    /// Write at `offset`, or at the end if it is `None`, and return where
+    /// `buf` went.
+    fn write(&self, offset: Option<usize>, buf: &[u8]) -> VfsResult<usize> {
+        let mut content = self.content.exclusive_access();
+        let (frames, size) = match &mut *content {
+            Content::File { frames, size } => (frames, size),
+            Content::Dir(_) => return Err(FsError::IsDir.into()),
+        };
+        let offset = offset.unwrap_or(*size);
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| end <= MAX_FILE_SIZE)
+            .ok_or(FsError::NoSpace)?;
+        let old_len = frames.len();
+        while frames.len() * PAGE_SIZE < end {
+            match frame_alloc() {
+                Some(frame) => frames.push(frame),
+                None => {
+                    frames.truncate(old_len);
+                    return Err(FsError::NoSpace.into());
+                }
+            }
+        }
+        let mut pos = offset;
+        while pos < end {
+            let page_offset = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - pos);
+            let page = frames[pos / PAGE_SIZE].ppn.get_bytes_array();
+            page[page_offset..page_offset + len]
+                .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        *size = (*size).max(end);
+        Ok(offset)
+    }
+}
+
+impl Inode for TmpInode {
+    fn inode_id(&self) -> usize {
+        self.id
+    }
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(matches!(*self.content.exclusive_access(), Content::Dir(_)))
+    }
+    fn size(&self) -> VfsResult<usize> {
+        Ok(match &*self.content.exclusive_access() {
+            Content::File { size, .. } => *size,
+            Content::Dir(_) => 0,
+        })
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content.exclusive_access();
+        let (frames, size) = match &*content {
+            Content::File { frames, size } => (frames, *size),
+            Content::Dir(_) => return Err(FsError::IsDir.into()),
+        };
+        let end = size.min(offset.saturating_add(buf.len()));
+        let mut pos = offset;
+        while pos < end {
+            let page_offset = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - pos);
+            let page = frames[pos / PAGE_SIZE].ppn.get_bytes_array();
+            buf[pos - offset..pos - offset + len]
+                .copy_from_slice(&page[page_offset..page_offset + len]);
+            pos += len;
+        }
+        Ok(end.saturating_sub(offset))
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> VfsResult<usize> {
+        self.write(Some(offset), buf)?;
+        Ok(buf.len())
+    }
+    fn append(&self, buf: &[u8]) -> VfsResult<usize> {
+        self.write(None, buf)
+    }
+    fn clear(&self) -> VfsResult {
+        match &mut *self.content.exclusive_access() {
+            Content::File { frames, size } => {
+                frames.clear();
+                *size = 0;
+                Ok(())
+            }
+            Content::Dir(_) => Err(FsError::IsDir.into()),
+        }
+    }
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        match &*self.content.exclusive_access() {
+            Content::Dir(children) => Ok(children
+                .get(name)
+                .map(|inode| inode.clone() as Arc<dyn Inode>)),
+            Content::File { .. } => Err(FsError::NotDir.into()),
+        }
+    }
+    fn create(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        self.add(
+            name,
+            Content::File {
+                frames: Vec::new(),
+                size: 0,
+            },
+        )
+    }
+    fn mkdir(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        self.add(name, Content::Dir(BTreeMap::new()))
+    }
+    /// Open files keep their content until closed.
+    fn unlink(&self, name: &str, dir: bool) -> VfsResult {
+        let mut content = self.content.exclusive_access();
+        let children = match &mut *content {
+            Content::Dir(children) => children,
+            Content::File { .. } => return Err(FsError::NotDir.into()),
+        };
+        let child = children.get(name).ok_or(FsError::NotFound)?;
+        match (&*child.content.exclusive_access(), dir) {
+            (Content::File { .. }, true) => return Err(FsError::NotDir.into()),
+            (Content::Dir(_), false) => return Err(FsError::IsDir.into()),
+            (Content::Dir(c), true) if !c.is_empty() => return Err(FsError::NotEmpty.into()),
+            _ => {}
+        }
+        children.remove(name);
+        Ok(())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>> {
+        match &*self.content.exclusive_access() {
+            Content::Dir(children) => Ok(children.keys().cloned().collect()),
+            Content::File { .. } => Err(FsError::NotDir.into()),
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Filesystem independent inodes and the mount table.
+
+use super::{File, Stat, VfsError, VfsResult, S_IFCHR, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, FsError, XattrMode};
+use lazy_static::*;
+
+/// One entry of a directory listing.
+pub struct DirEntry {
+    pub ino: usize,
+    /// The file type bits of `Stat::mode`.
+    pub mode: u32,
+    pub name: String,
+}
+
+/// A file or directory of some mounted filesystem.
+pub trait Inode: Send + Sync {
+    /// Unique within its filesystem.
+    fn inode_id(&self) -> usize;
+    fn is_dir(&self) -> VfsResult<bool>;
+    /// In bytes, 0 for directories whose filesystem does not track it.
+    fn size(&self) -> VfsResult<usize>;
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> VfsResult<usize>;
+    fn write_at(&self, offset: usize, buf: &[u8]) -> VfsResult<usize>;
+    /// Write at the end in one step, so appends through different open
+    /// files do not overwrite each other. Return the offset `buf` went to.
+    fn append(&self, buf: &[u8]) -> VfsResult<usize>;
+    /// Truncate to zero length.
+    fn clear(&self) -> VfsResult;
+    fn find(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>>;
+    /// Create a file, return `None` if `name` already exists.
+    fn create(&self, name: &str) -> VfsResult<Option<Arc<dyn Inode>>>;
+    /// Create a directory, return `None` if `name` already exists.
+    fn mkdir(&self, _name: &str) -> VfsResult<Option<Arc<dyn Inode>>> {
+        Err(FsError::Unsupported.into())
+    }
+    /// Remove the entry `name`, which must be a directory if `dir` is set and
+    /// must not be one otherwise. A directory only once it is empty.
+    fn unlink(&self, _name: &str, _dir: bool) -> VfsResult {
+        Err(FsError::Unsupported.into())
+    }
+    fn ls(&self) -> VfsResult<Vec<String>>;
+    fn get_xattr(&self, _name: &str) -> VfsResult<Vec<u8>> {
+        Err(FsError::Unsupported.into())
+    }
+    /// `Exists` if `mode` is `Create` and it exists, `NotFound` if `mode` is
+    /// `Replace` and it does not.
+    fn set_xattr(&self, _name: &str, _value: &[u8], _mode: XattrMode) -> VfsResult {
+        Err(FsError::Unsupported.into())
+    }
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+    fn remove_xattr(&self, _name: &str) -> VfsResult {
+        Err(FsError::Unsupported.into())
+    }
+    /// The target if this is a symbolic link.
+    fn read_link(&self) -> VfsResult<Option<String>> {
+        Ok(None)
+    }
+    /// Device nodes are opened as the device itself, not read via `read_at`.
+    fn device(&self) -> Option<Arc<dyn File + Send + Sync>> {
+        None
+    }
+    /// Built from the methods above, filesystems keeping link counts or
+    /// times override it.
+    fn stat(&self) -> VfsResult<Stat> {
+        let mode = if self.device().is_some() {
+            S_IFCHR | 0o666
+        } else if self.read_link()?.is_some() {
+            S_IFLNK | 0o777
+        } else if self.is_dir()? {
+            S_IFDIR | 0o755
+        } else {
+            S_IFREG | 0o644
+        };
+        Ok(Stat::new(self.inode_id(), mode, self.size()?))
+    }
+    /// The entries of `ls` with their inode and type, entries which vanish
+    /// meanwhile are left out.
+    fn dirents(&self) -> VfsResult<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for name in self.ls()? {
+            if let Some(inode) = self.find(&name)? {
+                let stat = inode.stat()?;
+                entries.push(DirEntry {
+                    ino: stat.ino as usize,
+                    mode: stat.mode & S_IFMT,
+                    name,
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// A mounted instance of a filesystem.
+pub trait SuperBlock: Send + Sync {
+    fn root_inode(&self) -> Arc<dyn Inode>;
+    /// Whether inodes are in use besides the root held here, e.g. by an open
+    /// file or a current directory.
+    fn busy(&self) -> bool {
+        false
+    }
+    /// Write everything back once removed from the mount table.
+    fn unmount(&self) -> VfsResult {
+        Ok(())
+    }
+}
+
+/// A kind of filesystem which can be mounted by name.
+pub trait FileSystemType: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// `device` is `None` for filesystems without backing storage.
+    fn mount(&self, device: Option<Arc<dyn BlockDevice>>) -> VfsResult<Arc<dyn SuperBlock>>;
+}
+
+struct Mount {
+    path: String,
+    fs_type: &'static str,
+    sb: Arc<dyn SuperBlock>,
+    device: Option<Arc<dyn BlockDevice>>,
+    /// The mount and inode id of the directory this filesystem covers,
+    /// `None` for the root filesystem.
+    covered: Option<(usize, usize)>,
+}
+
+#[derive(Default)]
+struct MountTable {
+    mounts: BTreeMap<usize, Mount>,
+    next_id: usize,
+}
+
+lazy_static! {
+    static ref FS_TYPES: UPIntrFreeCell<Vec<Arc<dyn FileSystemType>>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    static ref MOUNT_TABLE: UPIntrFreeCell<MountTable> =
+        unsafe { UPIntrFreeCell::new(MountTable::default()) };
+}
+
+pub fn register_filesystem(fs_type: Arc<dyn FileSystemType>) {
+    FS_TYPES.exclusive_access().push(fs_type);
+}
+
+/// An inode together with the mount it was reached through.
+#[derive(Clone)]
+struct Location {
+    mount: usize,
+    inode: Arc<dyn Inode>,
+    /// The name it was found by, empty for the root.
+    name: String,
+}
+
+/// Step onto the root of whatever is mounted on `loc`, repeatedly.
+fn cross_mounts(mut loc: Location) -> Location {
+    loop {
+        let covered = Some((loc.mount, loc.inode.inode_id()));
+        // the table must not stay borrowed while inodes do I/O
+        let above = MOUNT_TABLE
+            .exclusive_access()
+            .mounts
+            .iter()
+            .find(|(_, mount)| mount.covered == covered)
+            .map(|(id, mount)| (*id, mount.sb.clone()));
+        match above {
+            Some((mount, sb)) => {
+                loc = Location {
+                    mount,
+                    inode: sb.root_inode(),
+                    name: loc.name,
+                }
+            }
+            None => return loc,
+        }
+    }
+}
+
+/// Symlinks followed in one lookup before giving up, as in Linux.
+const MAX_SYMLINKS: usize = 40;
+
+fn root() -> VfsResult<Location> {
+    let root = MOUNT_TABLE
+        .exclusive_access()
+        .mounts
+        .iter()
+        .find(|(_, mount)| mount.covered.is_none())
+        .map(|(id, mount)| (*id, mount.sb.clone()));
+    let (mount, sb) = root.ok_or(FsError::NotFound)?;
+    Ok(cross_mounts(Location {
+        mount,
+        inode: sb.root_inode(),
+        name: String::new(),
+    }))
+}
+
+/// Resolve `path` from `cwd`, or from the root if it is absolute, returning
+/// every directory passed through so that ".." can go back up, also across
+/// mount points. Symlinks are followed, the last name only if `follow_last`
+/// is set.
+fn walk(cwd: Option<&WorkDir>, path: &str, follow_last: bool) -> VfsResult<Vec<Location>> {
+    let mut stack = match cwd {
+        Some(cwd) if !path.starts_with('/') => cwd.stack.clone(),
+        _ => vec![root()?],
+    };
+    let mut names: VecDeque<String> = path.split('/').map(String::from).collect();
+    let mut symlinks = 0;
+    while let Some(name) = names.pop_front() {
+        match name.as_str() {
+            "" | "." => {}
+            ".." => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+            }
+            name => {
+                let dir = stack.last().unwrap();
+                let mount = dir.mount;
+                let inode = dir.inode.find(name)?.ok_or(FsError::NotFound)?;
+                let name = String::from(name);
+                let follow = follow_last || !names.is_empty();
+                if let Some(target) = inode.read_link()?.filter(|_| follow) {
+                    symlinks += 1;
+                    if symlinks > MAX_SYMLINKS {
+                        return Err(FsError::InvalidArgument.into());
+                    }
+                    if target.starts_with('/') {
+                        stack.truncate(1);
+                    }
+                    for name in target.split('/').rev() {
+                        names.push_front(String::from(name));
+                    }
+                    continue;
+                }
+                stack.push(cross_mounts(Location { mount, inode, name }));
+            }
+        }
+    }
+    Ok(stack)
+}
+
+/// A current directory. It holds every directory from the root down to it,
+/// so relative paths and ".." do not look it up again.
+#[derive(Clone)]
+pub struct WorkDir {
+    stack: Vec<Location>,
+}
+
+impl WorkDir {
+    pub fn root() -> VfsResult<Self> {
+        Ok(Self {
+            stack: vec![root()?],
+        })
+    }
+    pub fn inode(&self) -> Arc<dyn Inode> {
+        self.stack.last().unwrap().inode.clone()
+    }
+    /// Absolute and without symlinks.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for loc in &self.stack[1..] {
+            path.push('/');
+            path.push_str(&loc.name);
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+    /// The directory `path` leads to from here.
+    pub fn chdir(&self, path: &str) -> VfsResult<Self> {
+        let stack = walk(Some(self), path, true)?;
+        if !stack.last().unwrap().inode.is_dir()? {
+            return Err(FsError::NotDir.into());
+        }
+        Ok(Self { stack })
+    }
+}
+
+/// Resolve `path`, relative paths start from `cwd`.
+pub fn lookup(cwd: &WorkDir, path: &str) -> VfsResult<Arc<dyn Inode>> {
+    Ok(walk(Some(cwd), path, true)?.pop().unwrap().inode)
+}
+
+/// Like `lookup`, but give a symlink at the end of `path` itself.
+pub fn lookup_nofollow(cwd: &WorkDir, path: &str) -> VfsResult<Arc<dyn Inode>> {
+    Ok(walk(Some(cwd), path, false)?.pop().unwrap().inode)
+}
+
+/// Split `path` into its directory and last name.
+fn split_parent(path: &str) -> VfsResult<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        // keep the slash, "/name" is in the root
+        Some(pos) => (&path[..pos + 1], &path[pos + 1..]),
+        None => ("", path),
+    };
+    if matches!(name, "" | "." | "..") {
+        return Err(FsError::InvalidArgument.into());
+    }
+    Ok((dir, name))
+}
+
+/// Resolve the directory containing `path` and return it with the last name.
+pub fn lookup_parent<'a>(cwd: &WorkDir, path: &'a str) -> VfsResult<(Arc<dyn Inode>, &'a str)> {
+    let (dir, name) = split_parent(path)?;
+    Ok((lookup(cwd, dir)?, name))
+}
+
+/// Remove the entry `path` leads to, which must be a directory if `dir` is
+/// set and must not be one otherwise. Mount points stay.
+pub fn unlink(cwd: &WorkDir, path: &str, dir: bool) -> VfsResult {
+    let (dir_path, name) = split_parent(path)?;
+    let parent = walk(Some(cwd), dir_path, true)?.pop().unwrap();
+    let inode = parent.inode.find(name)?.ok_or(FsError::NotFound)?;
+    let covered = Some((parent.mount, inode.inode_id()));
+    let mount_point = MOUNT_TABLE
+        .exclusive_access()
+        .mounts
+        .values()
+        .any(|mount| mount.covered == covered);
+    if mount_point {
+        return Err(VfsError::Busy);
+    }
+    parent.inode.unlink(name, dir)
+}
+
+/// Remove ".", ".." and repeated slashes from `path`.
+pub fn normalize(path: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    let mut normalized = String::new();
+    for name in names {
+        normalized.push('/');
+        normalized.push_str(name);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+fn same_device(a: &Arc<dyn BlockDevice>, b: &Arc<dyn BlockDevice>) -> bool {
+    Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+}
+
+/// Mount a filesystem of type `fs_type` on the directory `path`.
+/// The first mount must be the root filesystem at "/".
+pub fn mount(path: &str, fs_type: &str, device: Option<Arc<dyn BlockDevice>>) -> VfsResult {
+    let fs_type = FS_TYPES
+        .exclusive_access()
+        .iter()
+        .find(|t| t.name() == fs_type)
+        .cloned()
+        .ok_or(FsError::Unsupported)?;
+    let path = normalize(path);
+    // two instances of one filesystem would overwrite each other's metadata
+    if let Some(device) = &device {
+        let table = MOUNT_TABLE.exclusive_access();
+        let mut mounted = table.mounts.values().filter_map(|m| m.device.as_ref());
+        if mounted.any(|other| same_device(other, device)) {
+            return Err(VfsError::Busy);
+        }
+    }
+    let is_root = MOUNT_TABLE.exclusive_access().mounts.is_empty();
+    let covered = if is_root {
+        if path != "/" {
+            return Err(FsError::NotFound.into());
+        }
+        None
+    } else {
+        let loc = walk(None, &path, true)?.pop().unwrap();
+        if !loc.inode.is_dir()? {
+            return Err(FsError::NotDir.into());
+        }
+        Some((loc.mount, loc.inode.inode_id()))
+    };
+    let sb = fs_type.mount(device.clone())?;
+    let mut table = MOUNT_TABLE.exclusive_access();
+    let id = table.next_id;
+    table.next_id += 1;
+    table.mounts.insert(
+        id,
+        Mount {
+            path,
+            fs_type: fs_type.name(),
+            sb,
+            device,
+            covered,
+        },
+    );
+    Ok(())
+}
+
+/// Unmount the filesystem mounted on `path`. The root filesystem,
+/// filesystems with other mounts below them and filesystems whose inodes are
+/// still in use stay mounted.
+pub fn umount(path: &str) -> VfsResult {
+    let loc = walk(None, path, true)?.pop().unwrap();
+    let (id, inode_id) = (loc.mount, loc.inode.inode_id());
+    // our own reference would count as a user
+    drop(loc);
+    let mut table = MOUNT_TABLE.exclusive_access();
+    let mount = table.mounts.get(&id).unwrap();
+    let is_mount_root = inode_id == mount.sb.root_inode().inode_id();
+    if !is_mount_root || mount.covered.is_none() {
+        return Err(FsError::InvalidArgument.into());
+    }
+    let covered = table
+        .mounts
+        .values()
+        .any(|other| matches!(other.covered, Some((covered, _)) if covered == id));
+    // a later mount of the device would allocate blocks the old inodes use
+    if covered || mount.sb.busy() {
+        return Err(VfsError::Busy);
+    }
+    let mount = table.mounts.remove(&id).unwrap();
+    drop(table);
+    mount.sb.unmount()
+}
+
+/// Mount points and filesystem type names, in mount order.
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNT_TABLE
+        .exclusive_access()
+        .mounts
+        .values()
+        .map(|mount| (mount.path.clone(), mount.fs_type))
+        .collect()
+}
@@ -19,6 +19,12 @@ pub fn init_heap() {
     }
 }
 
+/// Return (bytes in use, total bytes) of the kernel heap.
+pub fn heap_stats() -> (usize, usize) {
+    let heap = HEAP_ALLOCATOR.lock();
+    (heap.stats_alloc_actual(), heap.stats_total_bytes())
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
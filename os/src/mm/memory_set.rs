@@ -242,6 +242,19 @@ impl MemorySet {
         //*self = Self::new_bare();
         self.areas.clear();
     }
+    /// Start, end and permission of each area.
+    pub fn areas(&self) -> Vec<(VirtAddr, VirtAddr, MapPermission)> {
+        self.areas
+            .iter()
+            .map(|area| {
+                (
+                    area.vpn_range.get_start().into(),
+                    area.vpn_range.get_end().into(),
+                    area.map_perm,
+                )
+            })
+            .collect()
+    }
 }
 
 pub struct MapArea {
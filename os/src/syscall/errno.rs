@@ -0,0 +1,90 @@
+//! Errors of system calls, returned to user space as negated Linux errnos.
+
+use crate::fs::VfsError;
+use easy_fs::FsError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysError {
+    /// ENOENT
+    NotFound = 2,
+    /// ESRCH
+    NoProcess = 3,
+    /// EIO
+    Io = 5,
+    /// ENOEXEC
+    NoExec = 8,
+    /// EBADF
+    BadFd = 9,
+    /// ECHILD
+    NoChild = 10,
+    /// EAGAIN, e.g. waiting for a child which is still running.
+    Again = 11,
+    /// EBUSY
+    Busy = 16,
+    /// EEXIST
+    Exists = 17,
+    /// ENODEV
+    NoDevice = 19,
+    /// ENOTDIR
+    NotDir = 20,
+    /// EISDIR
+    IsDir = 21,
+    /// EINVAL
+    Invalid = 22,
+    /// ENOSPC
+    NoSpace = 28,
+    /// ESPIPE
+    IllegalSeek = 29,
+    /// EROFS
+    ReadOnly = 30,
+    /// ERANGE, a user buffer is too small for the result.
+    Range = 34,
+    /// EDEADLK
+    Deadlock = 35,
+    /// ENOSYS
+    NoSys = 38,
+    /// ENOTEMPTY
+    NotEmpty = 39,
+    /// ENODATA, e.g. no such extended attribute.
+    NoData = 61,
+    /// EOPNOTSUPP
+    NotSupported = 95,
+    /// EUCLEAN, which Linux filesystems report for corrupted metadata.
+    Corrupted = 117,
+}
+
+pub type SysResult<T = usize> = Result<T, SysError>;
+
+impl SysError {
+    pub fn errno(self) -> isize {
+        self as isize
+    }
+}
+
+impl From<FsError> for SysError {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::Corrupted => Self::Corrupted,
+            FsError::NotFound => Self::NotFound,
+            FsError::NoSpace => Self::NoSpace,
+            FsError::InvalidArgument => Self::Invalid,
+            FsError::Unsupported => Self::NotSupported,
+            FsError::ReadOnly => Self::ReadOnly,
+            FsError::Io => Self::Io,
+            FsError::NotDir => Self::NotDir,
+            FsError::IsDir => Self::IsDir,
+            FsError::NotEmpty => Self::NotEmpty,
+        }
+    }
+}
+
+impl From<VfsError> for SysError {
+    fn from(err: VfsError) -> Self {
+        match err {
+            VfsError::Fs(err) => err.into(),
+            VfsError::NotSeekable => Self::IllegalSeek,
+            VfsError::Exists => Self::Exists,
+            VfsError::Busy => Self::Busy,
+        }
+    }
+}
@@ -1,76 +1,187 @@
-use crate::fs::{make_pipe, open_file, OpenFlags};
+use super::{SysError, SysResult};
+use crate::drivers::find_block_device;
+use crate::fs::{
+    find_inode, find_inode_nofollow, lookup_parent, make_pipe, mount, open, umount, unlink,
+    DirEntry, File, OpenFlags, SeekFrom, VfsError, WorkDir,
+};
 use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
 use crate::task::{current_process, current_user_token};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{FsError, XattrMode};
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    let token = current_user_token();
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+
+fn fd_file(fd: usize) -> SysResult<Arc<dyn File + Send + Sync>> {
     let process = current_process();
     let inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
-        if !file.writable() {
-            return -1;
+    let file = inner.fd_table.get(fd).and_then(|file| file.clone());
+    file.ok_or(SysError::BadFd)
+}
+
+fn cwd() -> WorkDir {
+    current_process().inner_exclusive_access().cwd.clone()
+}
+
+/// Copy `data` into user memory at `buf`, which may cross pages.
+fn copy_out(token: usize, data: &[u8], buf: *mut u8) {
+    let buffer = UserBuffer::new(translated_byte_buffer(token, buf, data.len()));
+    for (dst, src) in buffer.into_iter().zip(data.iter()) {
+        unsafe {
+            *dst = *src;
         }
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
     }
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     let token = current_user_token();
-    let process = current_process();
-    let inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let file = fd_file(fd)?;
+    if !file.writable() {
+        return Err(SysError::BadFd);
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        if !file.readable() {
-            return -1;
-        }
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
+    Ok(file.write(UserBuffer::new(translated_byte_buffer(token, buf, len)))?)
+}
+
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let token = current_user_token();
+    let file = fd_file(fd)?;
+    if !file.readable() {
+        return Err(SysError::BadFd);
     }
+    Ok(file.read(UserBuffer::new(translated_byte_buffer(token, buf, len)))?)
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    let process = current_process();
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    let file = fd_file(fd)?;
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return Err(SysError::Invalid),
+    };
+    Ok(file.seek(pos)?)
+}
+
+pub fn sys_pread(fd: usize, buf: *const u8, len: usize, offset: usize) -> SysResult {
+    let token = current_user_token();
+    let file = fd_file(fd)?;
+    if !file.readable() {
+        return Err(SysError::BadFd);
+    }
+    let buffer = UserBuffer::new(translated_byte_buffer(token, buf, len));
+    Ok(file.read_at(offset, buffer)?)
+}
+
+pub fn sys_pwrite(fd: usize, buf: *const u8, len: usize, offset: usize) -> SysResult {
+    let token = current_user_token();
+    let file = fd_file(fd)?;
+    if !file.writable() {
+        return Err(SysError::BadFd);
+    }
+    let buffer = UserBuffer::new(translated_byte_buffer(token, buf, len));
+    Ok(file.write_at(offset, buffer)?)
+}
+
+pub fn sys_fstat(fd: usize, statbuf: *mut u8) -> SysResult {
+    let token = current_user_token();
+    let stat = fd_file(fd)?.stat()?;
+    copy_out(token, stat.as_bytes(), statbuf);
+    Ok(0)
+}
+
+const AT_FDCWD: isize = -100;
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// Where a relative `path` of the `*at` calls starts: the directory open as
+/// `dirfd`, or the current directory for `AT_FDCWD`.
+fn at_dir(dirfd: isize, path: &str) -> SysResult<WorkDir> {
+    if dirfd == AT_FDCWD || path.starts_with('/') {
+        return Ok(cwd());
+    }
+    fd_file(dirfd as usize)?.work_dir().ok_or(SysError::NotDir)
+}
+
+pub fn sys_fstatat(dirfd: isize, path: *const u8, statbuf: *mut u8, flags: u32) -> SysResult {
     let token = current_user_token();
     let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return Err(SysError::Invalid);
+    }
+    let dir = at_dir(dirfd, path.as_str())?;
+    let inode = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        find_inode_nofollow(&dir, path.as_str())?
     } else {
-        -1
+        find_inode(&dir, path.as_str())?
+    };
+    copy_out(token, inode.stat()?.as_bytes(), statbuf);
+    Ok(0)
+}
+
+/// Size of `struct linux_dirent64` without the name.
+const DIRENT64_HEADER: usize = 19;
+
+/// Fill `buf` with `struct linux_dirent64` records, each `d_off` can be
+/// passed to `lseek` to continue after it. Return 0 at the end.
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> SysResult {
+    let token = current_user_token();
+    let file = fd_file(fd)?;
+    let mut data = Vec::new();
+    let mut too_small = false;
+    let mut emit = |next: usize, entry: &DirEntry| {
+        // the name is NUL-terminated, records are 8 byte aligned
+        let reclen = (DIRENT64_HEADER + entry.name.len() + 1 + 7) / 8 * 8;
+        if data.len() + reclen > len {
+            too_small = data.is_empty();
+            return false;
+        }
+        let start = data.len();
+        data.extend_from_slice(&(entry.ino as u64).to_ne_bytes());
+        data.extend_from_slice(&(next as i64).to_ne_bytes());
+        data.extend_from_slice(&(reclen as u16).to_ne_bytes());
+        data.push((entry.mode >> 12) as u8);
+        data.extend_from_slice(entry.name.as_bytes());
+        data.resize(start + reclen, 0);
+        true
+    };
+    file.read_dir(&mut emit)?;
+    if too_small {
+        return Err(SysError::Invalid);
     }
+    copy_out(token, &data, buf);
+    Ok(data.len())
 }
 
-pub fn sys_close(fd: usize) -> isize {
+pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32) -> SysResult {
     let process = current_process();
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let dir = at_dir(dirfd, path.as_str())?;
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::Invalid)?;
+    let file = open(&dir, path.as_str(), flags)?;
     let mut inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(file);
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.cloexec.insert(fd);
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    Ok(fd)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.fd_table.get(fd).map_or(true, Option::is_none) {
+        return Err(SysError::BadFd);
     }
     inner.fd_table[fd].take();
-    0
+    inner.cloexec.remove(&fd);
+    Ok(0)
 }
 
-pub fn sys_pipe(pipe: *mut usize) -> isize {
+pub fn sys_pipe(pipe: *mut usize) -> SysResult {
     let process = current_process();
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
@@ -81,19 +192,200 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     inner.fd_table[write_fd] = Some(pipe_write);
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    Ok(0)
 }
 
-pub fn sys_dup(fd: usize) -> isize {
+pub fn sys_dup(fd: usize) -> SysResult {
+    let file = fd_file(fd)?;
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
+}
+
+/// Bounds the fd table, which `sys_dup3` grows up to `newfd`.
+const FD_LIMIT: usize = 1024;
+
+/// Make `newfd` refer to the file of `oldfd`, closing whatever it referred to.
+pub fn sys_dup3(oldfd: usize, newfd: usize, flags: u32) -> SysResult {
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::Invalid)?;
+    if oldfd == newfd || !(flags - OpenFlags::CLOEXEC).is_empty() {
+        return Err(SysError::Invalid);
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    if newfd >= FD_LIMIT {
+        return Err(SysError::BadFd);
     }
-    let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = inner.fd_table.get(oldfd).and_then(|file| file.clone());
+    let file = file.ok_or(SysError::BadFd)?;
+    if inner.fd_table.len() <= newfd {
+        inner.fd_table.resize(newfd + 1, None);
+    }
+    inner.fd_table[newfd] = Some(file);
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.cloexec.insert(newfd);
+    } else {
+        inner.cloexec.remove(&newfd);
+    }
+    Ok(newfd)
+}
+
+const XATTR_CREATE: u32 = 1;
+const XATTR_REPLACE: u32 = 2;
+
+/// Copy `data` into the user buffer, only report the size if `size` is 0.
+fn copy_xattr_out(token: usize, data: &[u8], buf: *mut u8, size: usize) -> SysResult {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if size < data.len() {
+        return Err(SysError::Range);
+    }
+    copy_out(token, data, buf);
+    Ok(data.len())
+}
+
+/// A missing attribute is `NoData`, unlike a missing file.
+fn xattr_error(err: VfsError) -> SysError {
+    match err {
+        VfsError::Fs(FsError::NotFound) => SysError::NoData,
+        err => err.into(),
+    }
+}
+
+pub fn sys_setxattr(
+    path: *const u8,
+    name: *const u8,
+    value: *const u8,
+    size: usize,
+    flags: u32,
+) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let mode = match flags {
+        0 => XattrMode::Set,
+        XATTR_CREATE => XattrMode::Create,
+        XATTR_REPLACE => XattrMode::Replace,
+        _ => return Err(SysError::Invalid),
+    };
+    let inode = find_inode(&cwd(), path.as_str())?;
+    let value: Vec<u8> = UserBuffer::new(translated_byte_buffer(token, value, size))
+        .into_iter()
+        .map(|byte| unsafe { *byte })
+        .collect();
+    inode
+        .set_xattr(name.as_str(), &value, mode)
+        .map_err(xattr_error)?;
+    Ok(0)
+}
+
+pub fn sys_getxattr(path: *const u8, name: *const u8, value: *mut u8, size: usize) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let inode = find_inode(&cwd(), path.as_str())?;
+    let data = inode.get_xattr(name.as_str()).map_err(xattr_error)?;
+    copy_xattr_out(token, &data, value, size)
+}
+
+/// Names are written one after another, each terminated by '\0'.
+pub fn sys_listxattr(path: *const u8, list: *mut u8, size: usize) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let mut data = Vec::new();
+    for name in find_inode(&cwd(), path.as_str())?.list_xattr()? {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+    }
+    copy_xattr_out(token, &data, list, size)
+}
+
+pub fn sys_removexattr(path: *const u8, name: *const u8) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let inode = find_inode(&cwd(), path.as_str())?;
+    inode.remove_xattr(name.as_str()).map_err(xattr_error)?;
+    Ok(0)
+}
+
+/// Only block devices can be mounted, `source` names one like "/dev/vdb" or "vdb".
+/// No mount flags are supported yet.
+pub fn sys_mount(source: *const u8, target: *const u8, fstype: *const u8, flags: u32) -> SysResult {
+    let token = current_user_token();
+    let source = translated_str(token, source);
+    let target = translated_str(token, target);
+    let fstype = translated_str(token, fstype);
+    if flags != 0 {
+        return Err(SysError::Invalid);
+    }
+    let name = source.strip_prefix("/dev/").unwrap_or(&source);
+    let device = find_block_device(name).ok_or(SysError::NoDevice)?;
+    let target = cwd().chdir(target.as_str())?.path();
+    mount(target.as_str(), fstype.as_str(), Some(device))?;
+    Ok(0)
+}
+
+/// Cached blocks are written back before returning.
+pub fn sys_umount2(target: *const u8, flags: u32) -> SysResult {
+    let token = current_user_token();
+    let target = translated_str(token, target);
+    if flags != 0 {
+        return Err(SysError::Invalid);
+    }
+    let target = cwd().chdir(target.as_str())?.path();
+    umount(target.as_str())?;
+    Ok(0)
+}
+
+/// Write the current directory and a '\0', return their length.
+pub fn sys_getcwd(buf: *mut u8, size: usize) -> SysResult {
+    let token = current_user_token();
+    let mut path = cwd().path().into_bytes();
+    path.push(0);
+    if path.len() > size {
+        return Err(SysError::Range);
+    }
+    copy_out(token, &path, buf);
+    Ok(path.len())
+}
+
+pub fn sys_chdir(path: *const u8) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let dir = cwd().chdir(path.as_str())?;
+    current_process().inner_exclusive_access().cwd = dir;
+    Ok(0)
+}
+
+/// `fd` must be a directory.
+pub fn sys_fchdir(fd: usize) -> SysResult {
+    let dir = fd_file(fd)?.work_dir().ok_or(SysError::NotDir)?;
+    current_process().inner_exclusive_access().cwd = dir;
+    Ok(0)
+}
+
+/// `mode` is ignored, the filesystem picks the permissions.
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, _mode: u32) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let dir = at_dir(dirfd, path.as_str())?;
+    let (parent, name) = lookup_parent(&dir, path.as_str())?;
+    parent.mkdir(name)?.ok_or(SysError::Exists)?;
+    Ok(0)
+}
+
+/// Remove a file, or an empty directory with `AT_REMOVEDIR`.
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: u32) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    if flags & !AT_REMOVEDIR != 0 {
+        return Err(SysError::Invalid);
+    }
+    let dir = at_dir(dirfd, path.as_str())?;
+    unlink(&dir, path.as_str(), flags & AT_REMOVEDIR != 0)?;
+    Ok(0)
 }
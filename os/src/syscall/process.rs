@@ -1,3 +1,4 @@
+use super::{SysError, SysResult};
 use crate::fs::{open_file, OpenFlags};
 use crate::mm::{translated_ref, translated_refmut, translated_str};
 use crate::task::{
@@ -14,20 +15,20 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+pub fn sys_get_time() -> SysResult {
+    Ok(get_time_ms())
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().process.upgrade().unwrap().getpid() as isize
+pub fn sys_getpid() -> SysResult {
+    Ok(current_task().unwrap().process.upgrade().unwrap().getpid())
 }
 
-pub fn sys_fork() -> isize {
+pub fn sys_fork() -> SysResult {
     let current_process = current_process();
     let new_process = current_process.fork();
     let new_pid = new_process.getpid();
@@ -38,10 +39,10 @@ pub fn sys_fork() -> isize {
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.x[10] = 0;
-    new_pid as isize
+    Ok(new_pid)
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
+pub fn sys_exec(path: *const u8, mut args: *const usize) -> SysResult {
     let token = current_user_token();
     let path = translated_str(token, path);
     let mut args_vec: Vec<String> = Vec::new();
@@ -55,21 +56,22 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
             args = args.add(1);
         }
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let process = current_process();
-        let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
-    } else {
-        -1
+    let process = current_process();
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let app_inode = open_file(&cwd, path.as_str(), OpenFlags::RDONLY)?;
+    let all_data = app_inode.read_all();
+    if !all_data.starts_with(b"\x7fELF") {
+        return Err(SysError::NoExec);
     }
+    let argc = args_vec.len();
+    process.exec(all_data.as_slice(), args_vec);
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc)
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// If there is not a child process whose pid is same as given, return `NoChild`.
+/// Else if there is a child process but it is still running, return `Again`.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> SysResult {
     let process = current_process();
     // find a child process
 
@@ -79,7 +81,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return Err(SysError::NoChild);
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
@@ -96,22 +98,16 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
         *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        Ok(found_pid)
     } else {
-        -2
+        Err(SysError::Again)
     }
     // ---- release current PCB automatically
 }
 
-pub fn sys_kill(pid: usize, signal: u32) -> isize {
-    if let Some(process) = pid2process(pid) {
-        if let Some(flag) = SignalFlags::from_bits(signal) {
-            process.inner_exclusive_access().signals |= flag;
-            0
-        } else {
-            -1
-        }
-    } else {
-        -1
-    }
+pub fn sys_kill(pid: usize, signal: u32) -> SysResult {
+    let process = pid2process(pid).ok_or(SysError::NoProcess)?;
+    let flag = SignalFlags::from_bits(signal).ok_or(SysError::Invalid)?;
+    process.inner_exclusive_access().signals |= flag;
+    Ok(0)
 }
@@ -1,11 +1,12 @@
+use super::{SysError, SysResult};
 use crate::{
-    mm::kernel_token,
+    mm::{kernel_token, translated_refmut},
     task::{add_task, current_task, TaskControlBlock},
     trap::{trap_handler, TrapContext},
 };
 use alloc::sync::Arc;
 
-pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     // create a new thread
@@ -39,47 +40,48 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         trap_handler as usize,
     );
     (*new_task_trap_cx).x[10] = arg;
-    new_task_tid as isize
+    Ok(new_task_tid)
 }
 
-pub fn sys_gettid() -> isize {
-    current_task()
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task()
         .unwrap()
         .inner_exclusive_access()
         .res
         .as_ref()
         .unwrap()
-        .tid as isize
+        .tid)
 }
 
-/// thread does not exist, return -1
-/// thread has not exited yet, return -2
-/// otherwise, return thread's exit code
-pub fn sys_waittid(tid: usize) -> i32 {
+/// thread does not exist, return `NoProcess`
+/// thread has not exited yet, return `Again`
+/// otherwise, write thread's exit code to `exit_code_ptr` and return its tid
+pub fn sys_waittid(tid: usize, exit_code_ptr: *mut i32) -> SysResult {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     let task_inner = task.inner_exclusive_access();
     let mut process_inner = process.inner_exclusive_access();
     // a thread cannot wait for itself
     if task_inner.res.as_ref().unwrap().tid == tid {
-        return -1;
+        return Err(SysError::Deadlock);
     }
     let mut exit_code: Option<i32> = None;
-    let waited_task = process_inner.tasks[tid].as_ref();
+    let waited_task = process_inner.tasks.get(tid).and_then(Option::as_ref);
     if let Some(waited_task) = waited_task {
         if let Some(waited_exit_code) = waited_task.inner_exclusive_access().exit_code {
             exit_code = Some(waited_exit_code);
         }
     } else {
         // waited thread does not exist
-        return -1;
+        return Err(SysError::NoProcess);
     }
     if let Some(exit_code) = exit_code {
         // dealloc the exited thread
         process_inner.tasks[tid] = None;
-        exit_code
+        *translated_refmut(process_inner.memory_set.token(), exit_code_ptr) = exit_code;
+        Ok(tid)
     } else {
         // waited thread has not exited
-        -2
+        Err(SysError::Again)
     }
 }
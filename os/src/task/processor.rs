@@ -2,6 +2,7 @@ use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
 use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -44,12 +45,15 @@ pub fn run_tasks() {
                 task_inner.task_status = TaskStatus::Running;
                 &task_inner.task_cx as *const TaskContext
             });
-            processor.current = Some(task);
+            processor.current = Some(task.clone());
             // release processor manually
             drop(processor);
+            let start = get_time();
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // back in the idle loop, charge the task for the time it ran
+            task.inner_exclusive_access().run_time += get_time() - start;
         } else {
             println!("no tasks available in run_tasks");
         }
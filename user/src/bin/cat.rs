@@ -5,16 +5,16 @@
 extern crate user_lib;
 extern crate alloc;
 
+use user_lib::errno::Errno;
 use user_lib::{close, open, read, OpenFlags};
 
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
     assert!(argc == 2);
-    let fd = open(argv[1], OpenFlags::RDONLY);
-    if fd == -1 {
-        panic!("Error occurred when opening file");
-    }
-    let fd = fd as usize;
+    let fd = match Errno::decode(open(argv[1], OpenFlags::RDONLY)) {
+        Ok(fd) => fd,
+        Err(err) => panic!("Error occurred when opening file: {}", err),
+    };
     let mut buf = [0u8; 256];
     loop {
         let size = read(fd, &mut buf) as usize;
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{ENOENT, ENOTDIR, ERANGE};
+use user_lib::{chdir, close, exit, fchdir, fork, getcwd, open, waitpid, OpenFlags};
+
+fn cwd(buf: &mut [u8; 64]) -> &str {
+    let len = getcwd(buf);
+    assert!(len > 0);
+    core::str::from_utf8(&buf[..len as usize - 1]).unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 64];
+    assert_eq!(chdir("/tmp\0"), 0);
+    assert_eq!(cwd(&mut buf), "/tmp");
+
+    // relative names start from the current directory
+    let fd = open("cwdtest\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("/tmp/cwdtest\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("../tmp/./cwdtest\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(cwd(&mut buf), "/");
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(cwd(&mut buf), "/");
+    assert_eq!(chdir("tmp/cwdtest\0"), -ENOTDIR);
+    assert_eq!(chdir("missing\0"), -ENOENT);
+    assert_eq!(cwd(&mut buf), "/");
+    assert_eq!(getcwd(&mut [0u8; 1]), -ERANGE);
+
+    let dir = open("/tmp\0", OpenFlags::RDONLY);
+    assert!(dir > 0);
+    let dir = dir as usize;
+    assert_eq!(fchdir(dir), 0);
+    assert_eq!(cwd(&mut buf), "/tmp");
+    close(dir);
+    assert_eq!(fchdir(1), -ENOTDIR);
+
+    // inherited by children, which can not change ours
+    let pid = fork();
+    if pid == 0 {
+        let mut buf = [0u8; 64];
+        assert_eq!(cwd(&mut buf), "/tmp");
+        assert_eq!(chdir("/\0"), 0);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(cwd(&mut buf), "/tmp");
+    println!("cwdtest passed!");
+    0
+}
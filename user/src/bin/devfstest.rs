@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{ENOENT, EOPNOTSUPP};
+use user_lib::{close, open, read, write, OpenFlags};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buffer = [0xffu8; 32];
+
+    let fd = open("/dev/null\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"discarded"), 9);
+    assert_eq!(read(fd, &mut buffer), 0);
+    close(fd);
+
+    let fd = open("/dev/zero\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(read(fd, &mut buffer), buffer.len() as isize);
+    assert!(buffer.iter().all(|&byte| byte == 0));
+    close(fd);
+
+    let fd = open("/dev/urandom\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(read(fd, &mut buffer), buffer.len() as isize);
+    assert!(buffer.iter().any(|&byte| byte != 0));
+    close(fd);
+
+    assert_eq!(open("/dev/nonexistent\0", OpenFlags::RDONLY), -ENOENT);
+    assert_eq!(
+        open("/dev/new\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -EOPNOTSUPP
+    );
+    println!("devfstest passed!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EBADF, EINVAL};
+use user_lib::{
+    close, dup2, dup3, exec, fork, fstat, open, read, unlink, waitpid, write, OpenFlags, Stat,
+};
+
+/// Past the end of the table until `dup3` grows it.
+const CLOEXEC_FD: usize = 30;
+const KEPT_FD: usize = 31;
+const CLEARED_FD: usize = 32;
+
+fn create(path: &str, data: &[u8]) -> usize {
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    fd as usize
+}
+
+/// Run as "dup3test child" after `exec`.
+fn child() -> i32 {
+    let mut st = Stat::default();
+    assert_eq!(fstat(CLOEXEC_FD, &mut st), -EBADF);
+    assert_eq!(fstat(KEPT_FD, &mut st), 0);
+    assert_eq!(fstat(CLEARED_FD, &mut st), 0);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 && argv[1] == "child" {
+        return child();
+    }
+    let a = create("/tmp/dup3a\0", b"a");
+    let b = create("/tmp/dup3b\0", b"b");
+
+    // the old file of newfd is closed, the new one shares its offset
+    assert_eq!(dup2(b, a), a as isize);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(a, &mut buf), 1);
+    assert_eq!(buf[0], b'b');
+    assert_eq!(read(b, &mut buf), 0);
+
+    assert_eq!(dup2(a, a), a as isize);
+    assert_eq!(dup2(99, a), -EBADF);
+    assert_eq!(dup2(a, 1 << 20), -EBADF);
+    assert_eq!(dup3(a, a, OpenFlags::empty()), -EINVAL);
+    assert_eq!(dup3(a, 40, OpenFlags::WRONLY), -EINVAL);
+
+    assert_eq!(dup3(a, CLOEXEC_FD, OpenFlags::CLOEXEC), CLOEXEC_FD as isize);
+    assert_eq!(dup2(a, KEPT_FD), KEPT_FD as isize);
+    assert_eq!(dup3(a, CLEARED_FD, OpenFlags::CLOEXEC), CLEARED_FD as isize);
+    assert_eq!(dup2(b, CLEARED_FD), CLEARED_FD as isize);
+    let pid = fork();
+    if pid == 0 {
+        let args = [
+            "dup3test\0".as_ptr(),
+            "child\0".as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("/dup3test\0", &args);
+        panic!("exec failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    for fd in [a, b, CLOEXEC_FD, KEPT_FD, CLEARED_FD] {
+        assert_eq!(close(fd), 0);
+    }
+    assert_eq!(unlink("/tmp/dup3a\0"), 0);
+    assert_eq!(unlink("/tmp/dup3b\0"), 0);
+    println!("dup3test passed!");
+    0
+}
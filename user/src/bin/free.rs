@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use user_lib::{proc_field, read_to_string};
+
+/// Value of a "Key:\t<n> kB" line.
+fn kb(meminfo: &str, key: &str) -> usize {
+    proc_field(meminfo, key)
+        .and_then(|value| value.trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let meminfo = match read_to_string("/proc/meminfo\0") {
+        Some(meminfo) => meminfo,
+        None => {
+            println!("free: can not read /proc/meminfo");
+            return -1;
+        }
+    };
+    let mem_total = kb(&meminfo, "MemTotal");
+    let mem_free = kb(&meminfo, "MemFree");
+    let heap_total = kb(&meminfo, "HeapTotal");
+    let heap_used = kb(&meminfo, "HeapUsed");
+    println!("{:>6} {:>10} {:>10} {:>10}", "", "total", "used", "free");
+    println!(
+        "{:>6} {:>10} {:>10} {:>10}",
+        "Mem:",
+        mem_total,
+        mem_total - mem_free,
+        mem_free
+    );
+    println!(
+        "{:>6} {:>10} {:>10} {:>10}",
+        "Heap:",
+        heap_total,
+        heap_used,
+        heap_total - heap_used
+    );
+    0
+}
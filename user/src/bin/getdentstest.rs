@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::errno::{EINVAL, EISDIR, ENOTDIR};
+use user_lib::{
+    close, getdents, list_dir, lseek, open, read, DirEntries, OpenFlags, DT_DIR, DT_REG, SEEK_SET,
+};
+
+const FILES: usize = 20;
+
+fn d_type(dir: &str, name: &str) -> Option<u8> {
+    let fd = open(dir, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buf = [0u8; 512];
+    let mut found = None;
+    while found.is_none() {
+        let len = getdents(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        found = DirEntries::new(&buf[..len as usize])
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.d_type);
+    }
+    close(fd);
+    found
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for i in 0..FILES {
+        let fd = open(&format!("/tmp/dirent{}\0", i), OpenFlags::CREATE);
+        assert!(fd > 0);
+        close(fd as usize);
+    }
+
+    // a small buffer takes several calls
+    let fd = open("/tmp\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buf = [0u8; 64];
+    let mut names: Vec<String> = Vec::new();
+    let mut second_off = 0;
+    loop {
+        let len = getdents(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in DirEntries::new(&buf[..len as usize]) {
+            if names.len() == 1 {
+                second_off = entry.off;
+            }
+            names.push(String::from(entry.name));
+        }
+    }
+    for i in 0..FILES {
+        assert!(names.contains(&format!("dirent{}", i)));
+    }
+
+    // offsets resume after the entry they belong to
+    assert_eq!(
+        lseek(fd, second_off as isize, SEEK_SET),
+        second_off as isize
+    );
+    let len = getdents(fd, &mut buf);
+    assert!(len > 0);
+    let entry = DirEntries::new(&buf[..len as usize]).next().unwrap();
+    assert_eq!(entry.name, names[2]);
+
+    lseek(fd, 0, SEEK_SET);
+    assert_eq!(getdents(fd, &mut [0u8; 8]), -EINVAL);
+    assert_eq!(read(fd, &mut buf), -EISDIR);
+    close(fd);
+
+    assert_eq!(list_dir("/tmp\0").unwrap().len(), names.len());
+    assert_eq!(d_type("/\0", "tmp"), Some(DT_DIR));
+    assert_eq!(d_type("/tmp\0", "dirent0"), Some(DT_REG));
+
+    let fd = open("/tmp/dirent0\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(getdents(fd, &mut buf), -ENOTDIR);
+    close(fd);
+    assert_eq!(open("/tmp\0", OpenFlags::WRONLY), -EISDIR);
+    assert_eq!(open("/tmp\0", OpenFlags::CREATE), -EISDIR);
+    println!("getdentstest passed!");
+    0
+}
@@ -15,7 +15,10 @@ fn worker(size_kib: usize) {
         *ch = i as u8;
     }
     let filename = format(format_args!("testf{}\0", gettid()));
-    let f = open(filename.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+    let f = open(
+        filename.as_str(),
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
     if f < 0 {
         panic!("Open test file failed!");
     }
@@ -43,7 +46,9 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
         v.push(thread_create(worker as usize, size_kb / workers));
     }
     for tid in v.iter() {
-        assert_eq!(0, waittid(*tid as usize));
+        let mut exit_code = 0;
+        waittid(*tid as usize, &mut exit_code);
+        assert_eq!(exit_code, 0);
     }
 
     let time_ms = (get_time() - start) as usize;
@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, getdents, open, stat, DirEntries, OpenFlags, Stat, DT_DIR, DT_LNK, S_IFCHR, S_IFDIR,
+    S_IFIFO, S_IFLNK, S_IFMT,
+};
+
+fn type_char(mode: u32) -> char {
+    match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFCHR => 'c',
+        S_IFIFO => 'p',
+        _ => '-',
+    }
+}
+
+fn show(path: &str, name: &str, long: bool) {
+    if !long {
+        println!("{}", name);
+        return;
+    }
+    let mut st = Stat::default();
+    if stat(&format!("{}\0", path), &mut st) != 0 {
+        println!("?{:>7} {:>9} {}", "?", "?", name);
+        return;
+    }
+    println!(
+        "{}{:>7} {:>9} {}",
+        type_char(st.mode),
+        st.ino,
+        st.size,
+        name
+    );
+}
+
+/// Entries with "/" appended to directories and "@" to symlinks.
+fn entries(path: &str) -> Option<Vec<(String, String)>> {
+    let fd = open(&format!("{}\0", path), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut entries = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for entry in DirEntries::new(&buf[..len as usize]) {
+            let suffix = match entry.d_type {
+                DT_DIR => "/",
+                DT_LNK => "@",
+                _ => "",
+            };
+            let child = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+            entries.push((child, format!("{}{}", entry.name, suffix)));
+        }
+    }
+    close(fd);
+    entries.sort();
+    Some(entries)
+}
+
+/// Usage: ls [-l] [path...], listing "/" by default.
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let long = argv[1..].contains(&"-l");
+    let mut paths: Vec<&str> = argv[1..].iter().copied().filter(|a| *a != "-l").collect();
+    if paths.is_empty() {
+        paths.push("/");
+    }
+    let mut status = 0;
+    for (i, path) in paths.iter().enumerate() {
+        let mut st = Stat::default();
+        if stat(&format!("{}\0", path), &mut st) != 0 {
+            println!("ls: {}: no such file or directory", path);
+            status = 1;
+            continue;
+        }
+        if !st.is_dir() {
+            show(path, path, long);
+            continue;
+        }
+        if paths.len() > 1 {
+            if i > 0 {
+                println!("");
+            }
+            println!("{}:", path);
+        }
+        match entries(path) {
+            Some(entries) => {
+                for (child, name) in entries {
+                    show(&child, &name, long);
+                }
+            }
+            None => {
+                println!("ls: {}: can not list", path);
+                status = 1;
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mount, umount};
+
+/// mount <device> <dir> [fstype]
+/// mount -u <dir>
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // argv strings are followed by '\0', so they can be passed on directly
+    let ret = match argc {
+        3 if argv[1] == "-u" => umount(argv[2]),
+        3 => mount(argv[1], argv[2], "easy-fs\0"),
+        4 => mount(argv[1], argv[2], argv[3]),
+        _ => {
+            println!("usage: mount <device> <dir> [fstype] | mount -u <dir>");
+            return -1;
+        }
+    };
+    if ret != 0 {
+        println!("mount: failed with {}", ret);
+        return -1;
+    }
+    0
+}
@@ -66,7 +66,8 @@ pub fn main() -> i32 {
     threads.push(thread_create(consumer as usize, 0));
     // wait for all threads to complete
     for thread in threads.iter() {
-        waittid(*thread as usize);
+        let mut exit_code = 0;
+        waittid(*thread as usize, &mut exit_code);
     }
     println!("mpsc_sem passed!");
     0
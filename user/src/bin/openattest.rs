@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::errno::{EBADF, EBUSY, EEXIST, EINVAL, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY};
+use user_lib::{
+    chdir, close, mkdir, mkdirat, open, openat, read, rmdir, stat, statat, unlink, unlinkat, write,
+    OpenFlags, Stat, AT_FDCWD, AT_REMOVEDIR,
+};
+
+/// Create, use and remove the directory `base`, which must be absolute.
+fn check(base: &str) {
+    let path = |name: &str| -> String { format!("{}{}\0", base, name) };
+    assert_eq!(mkdir(&path("")), 0);
+    assert_eq!(mkdir(&path("")), -EEXIST);
+    let dir = open(&path(""), OpenFlags::RDONLY);
+    assert!(dir > 0);
+
+    // relative to the directory, not to the current one
+    assert_eq!(chdir("/\0"), 0);
+    let fd = openat(dir, "f\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+    let mut st = Stat::default();
+    assert_eq!(statat(dir, "f\0", &mut st), 0);
+    assert_eq!(st.size, 5);
+    assert_eq!(stat("f\0", &mut st), -ENOENT);
+    let fd = openat(AT_FDCWD, &path("/f")[1..], OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // absolute paths ignore the directory
+    let fd = openat(1, &path("/f"), OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(openat(1, "f\0", OpenFlags::RDONLY), -ENOTDIR);
+    assert_eq!(openat(-5, "f\0", OpenFlags::RDONLY), -EBADF);
+
+    assert_eq!(mkdirat(dir, "sub\0"), 0);
+    let fd = openat(dir, "sub/g\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlinkat(dir, "sub\0", AT_REMOVEDIR), -ENOTEMPTY);
+    assert_eq!(unlinkat(dir, "sub\0", 0), -EISDIR);
+    assert_eq!(unlinkat(dir, "sub/g\0", AT_REMOVEDIR), -ENOTDIR);
+    assert_eq!(unlinkat(dir, "sub/g\0", 0), 0);
+    assert_eq!(unlinkat(dir, "sub/g\0", 0), -ENOENT);
+    assert_eq!(unlinkat(dir, "sub\0", AT_REMOVEDIR), 0);
+    assert_eq!(unlinkat(dir, ".\0", AT_REMOVEDIR), -EINVAL);
+
+    // an open file stays readable once unlinked
+    let fd = openat(dir, "f\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(unlink(&path("/f")), 0);
+    assert_eq!(statat(dir, "f\0", &mut st), -ENOENT);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd as usize, &mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+    close(fd as usize);
+
+    close(dir as usize);
+    assert_eq!(rmdir(&path("")), 0);
+    assert_eq!(stat(&path(""), &mut st), -ENOENT);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // on tmpfs and on the root filesystem
+    check("/tmp/at");
+    check("/at");
+    // mount points stay
+    assert_eq!(rmdir("/tmp\0"), -EBUSY);
+    println!("openattest passed!");
+    0
+}
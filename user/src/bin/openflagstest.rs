@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::errno::{EBADF, EEXIST, EINVAL, ENOTDIR};
+use user_lib::{
+    close, exec, fork, fstat, lseek, open, pread, pwrite, read, stat, unlink, waitpid, write,
+    OpenFlags, Stat, SEEK_SET,
+};
+
+fn size(path: &str) -> i64 {
+    let mut st = Stat::default();
+    assert_eq!(stat(path, &mut st), 0);
+    st.size
+}
+
+/// Run as "openflagstest child <kept fd> <cloexec fd>" after `exec`.
+fn child(kept: &str, cloexec: &str) -> i32 {
+    let mut st = Stat::default();
+    assert_eq!(fstat(kept.parse().unwrap(), &mut st), 0);
+    assert_eq!(fstat(cloexec.parse().unwrap(), &mut st), -EBADF);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 4 && argv[1] == "child" {
+        return child(argv[2], argv[3]);
+    }
+    let path = "/tmp/openflags\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+
+    // CREATE alone keeps the content, TRUNC clears it
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(size(path), 5);
+    assert_eq!(open(path, OpenFlags::CREATE | OpenFlags::EXCL), -EEXIST);
+    let fd = open(path, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(size(path), 0);
+
+    let fresh = "/tmp/openflags_excl\0";
+    let fd = open(
+        fresh,
+        OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlink(fresh), 0);
+
+    // every write goes to the end, pwrite still writes where it is told
+    let fd = open(path, OpenFlags::RDWR | OpenFlags::APPEND);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"ab"), 2);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"cd"), 2);
+    assert_eq!(pwrite(fd, b"X", 0), 1);
+    let mut buf = [0u8; 8];
+    assert_eq!(pread(fd, &mut buf, 0), 4);
+    assert_eq!(&buf[..4], b"Xbcd");
+    // another open file appends after what this one wrote
+    let other = open(path, OpenFlags::WRONLY | OpenFlags::APPEND);
+    assert!(other > 0);
+    assert_eq!(write(other as usize, b"ef"), 2);
+    assert_eq!(write(fd, b"gh"), 2);
+    assert_eq!(pread(fd, &mut buf, 0), 8);
+    assert_eq!(&buf, b"Xbcdefgh");
+    close(other as usize);
+    close(fd);
+    assert_eq!(size(path), 8);
+
+    assert_eq!(open(path, OpenFlags::DIRECTORY), -ENOTDIR);
+    assert_eq!(
+        open(
+            "/tmp/openflags_dir\0",
+            OpenFlags::CREATE | OpenFlags::DIRECTORY
+        ),
+        -EINVAL
+    );
+    let fd = open("/tmp\0", OpenFlags::DIRECTORY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // a read-only open can not write, whatever other flags are given
+    let fd = open(path, OpenFlags::CLOEXEC);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"x"), -EBADF);
+    assert_eq!(read(fd as usize, &mut buf), 8);
+    close(fd as usize);
+
+    let kept = open(path, OpenFlags::RDONLY);
+    let cloexec = open(path, OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+    assert!(kept > 0 && cloexec > 0);
+    let pid = fork();
+    if pid == 0 {
+        // still open after fork
+        let mut st = Stat::default();
+        assert_eq!(fstat(cloexec as usize, &mut st), 0);
+        let kept = format!("{}\0", kept);
+        let cloexec = format!("{}\0", cloexec);
+        let args = [
+            "openflagstest\0".as_ptr(),
+            "child\0".as_ptr(),
+            kept.as_ptr(),
+            cloexec.as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("/openflagstest\0", &args);
+        panic!("exec failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(kept as usize);
+    close(cloexec as usize);
+    assert_eq!(unlink(path), 0);
+    println!("openflagstest passed!");
+    0
+}
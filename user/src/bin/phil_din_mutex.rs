@@ -76,7 +76,8 @@ pub fn main() -> i32 {
         ));
     }
     for tid in v.iter() {
-        waittid(*tid as usize);
+        let mut exit_code = 0;
+        waittid(*tid as usize, &mut exit_code);
     }
     let time_cost = get_time_u() - start;
     println!("time cost = {}", time_cost);
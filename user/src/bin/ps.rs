@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{proc_field, proc_pids, read_to_string};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!(
+        "{:>5} {:>5} {:<9} {:>3} {:>8} CMD",
+        "PID", "PPID", "STATE", "THR", "TIME(ms)"
+    );
+    for pid in proc_pids() {
+        let status = match read_to_string(&format!("/proc/{}/status\0", pid)) {
+            Some(status) => status,
+            None => continue,
+        };
+        let field = |key| proc_field(&status, key).unwrap_or("?");
+        // arguments are NUL-separated, fall back to the name
+        let cmdline = read_to_string(&format!("/proc/{}/cmdline\0", pid))
+            .map(|cmdline| cmdline.trim_end_matches('\0').replace('\0', " "))
+            .filter(|cmdline| !cmdline.is_empty())
+            .unwrap_or_else(|| String::from(field("Name")));
+        println!(
+            "{:>5} {:>5} {:<9} {:>3} {:>8} {}",
+            pid,
+            field("PPid"),
+            field("State"),
+            field("Threads"),
+            field("RunTimeMs"),
+            cmdline
+        );
+    }
+    0
+}
@@ -38,7 +38,9 @@ pub fn main() -> i32 {
     }
     let mut time_cost = Vec::new();
     for tid in v.iter() {
-        time_cost.push(waittid(*tid));
+        let mut exit_code = 0;
+        waittid(*tid, &mut exit_code);
+        time_cost.push(exit_code);
     }
     println!("time cost is {}ms", get_time() - start);
     assert_eq!(unsafe { A }, PER_THREAD * THREAD_COUNT);
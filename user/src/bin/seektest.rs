@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EINVAL, ESPIPE};
+use user_lib::{
+    close, lseek, open, pipe, pread, pwrite, read, write, OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "/tmp/seektest\0",
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello, world"), 12);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 12);
+    assert_eq!(lseek(fd, 7, SEEK_SET), 7);
+    let mut buffer = [0u8; 16];
+    assert_eq!(read(fd, &mut buffer), 5);
+    assert_eq!(&buffer[..5], b"world");
+    assert_eq!(lseek(fd, -5, SEEK_END), 7);
+    assert_eq!(lseek(fd, -2, SEEK_CUR), 5);
+    assert_eq!(lseek(fd, -6, SEEK_CUR), -EINVAL);
+    assert_eq!(lseek(fd, 0, 3), -EINVAL);
+
+    // writing past the end leaves a hole of zeroes
+    assert_eq!(lseek(fd, 4, SEEK_END), 16);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 12, SEEK_SET), 12);
+    assert_eq!(read(fd, &mut buffer), 5);
+    assert_eq!(&buffer[..5], b"\0\0\0\0!");
+
+    // pread and pwrite leave the offset alone
+    assert_eq!(lseek(fd, 3, SEEK_SET), 3);
+    assert_eq!(pwrite(fd, b"W", 7), 1);
+    assert_eq!(pread(fd, &mut buffer[..5], 7), 5);
+    assert_eq!(&buffer[..5], b"World");
+    assert_eq!(pread(fd, &mut buffer, 100), 0);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 3);
+    assert_eq!(read(fd, &mut buffer[..2]), 2);
+    assert_eq!(&buffer[..2], b"lo");
+    close(fd);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_SET), -ESPIPE);
+    assert_eq!(pread(pipe_fd[0], &mut buffer, 0), -ESPIPE);
+    assert_eq!(pwrite(pipe_fd[1], b"x", 0), -ESPIPE);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("seektest passed!");
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EBADF, ENOENT};
+use user_lib::{
+    close, fstat, lstat, open, pipe, stat, write, OpenFlags, Stat, S_IFCHR, S_IFIFO, S_IFMT,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(
+        "/tmp/stattest\0",
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &[b'x'; 1000]), 1000);
+    let mut by_fd = Stat::default();
+    assert_eq!(fstat(fd, &mut by_fd), 0);
+    assert!(by_fd.is_file());
+    assert_eq!(by_fd.size, 1000);
+    assert!(by_fd.blocks * 512 >= 1000);
+    close(fd);
+
+    let mut by_path = Stat::default();
+    assert_eq!(stat("/tmp/stattest\0", &mut by_path), 0);
+    assert_eq!(by_path.ino, by_fd.ino);
+    assert_eq!(by_path.size, 1000);
+    // not a symlink, so both agree
+    let mut no_follow = Stat::default();
+    assert_eq!(lstat("/tmp/stattest\0", &mut no_follow), 0);
+    assert_eq!(no_follow.ino, by_path.ino);
+
+    assert_eq!(stat("/tmp\0", &mut by_path), 0);
+    assert!(by_path.is_dir());
+    assert_eq!(stat("/tmp/missing\0", &mut by_path), -ENOENT);
+    assert_eq!(fstat(100, &mut by_path), -EBADF);
+
+    // /dev/null is 1:3 as on Linux
+    assert_eq!(stat("/dev/null\0", &mut by_path), 0);
+    assert_eq!(by_path.mode & S_IFMT, S_IFCHR);
+    assert_eq!(by_path.rdev, (1 << 8) | 3);
+    assert_eq!(fstat(1, &mut by_path), 0);
+    assert_eq!(by_path.mode & S_IFMT, S_IFCHR);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fstat(pipe_fd[0], &mut by_path), 0);
+    assert_eq!(by_path.mode & S_IFMT, S_IFIFO);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("stattest passed!");
+    0
+}
@@ -38,7 +38,8 @@ pub fn main() -> i32 {
     ];
     // wait for all threads to complete
     for thread in threads.iter() {
-        waittid(*thread as usize);
+        let mut exit_code = 0;
+        waittid(*thread as usize, &mut exit_code);
     }
     println!("sync_sem passed!");
     0
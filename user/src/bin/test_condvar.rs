@@ -52,7 +52,8 @@ pub fn main() -> i32 {
     ];
     // wait for all threads to complete
     for thread in threads.iter() {
-        waittid(*thread as usize);
+        let mut exit_code = 0;
+        waittid(*thread as usize, &mut exit_code);
     }
     println!("test_condvar passed!");
     0
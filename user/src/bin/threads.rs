@@ -26,7 +26,8 @@ pub fn thread_c() -> ! {
     for _ in 0..1000 {
         print!("c");
     }
-    exit(3)
+    // negative codes are not mistaken for errors
+    exit(-3)
 }
 
 #[no_mangle]
@@ -36,9 +37,11 @@ pub fn main() -> i32 {
         thread_create(thread_b as usize, 0),
         thread_create(thread_c as usize, 0),
     ];
-    for tid in v.iter() {
-        let exit_code = waittid(*tid as usize);
+    for (tid, expected) in v.iter().zip([1, 2, -3]) {
+        let mut exit_code = 0;
+        assert_eq!(waittid(*tid as usize, &mut exit_code), *tid);
         println!("thread#{} exited with code {}", tid, exit_code);
+        assert_eq!(exit_code, expected);
     }
     println!("main thread exited.");
     0
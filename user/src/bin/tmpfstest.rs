@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::errno::{ENOENT, ENOSPC, ENOTDIR};
+use user_lib::{close, fstat, open, pwrite, read, write, OpenFlags, Stat};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // crosses page boundaries in both the write and the reads
+    let mut data = [0u8; 5000];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+    for i in 0..32 {
+        let path = format!("/tmp/scratch{}\0", i);
+        let fd = open(&path, OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        let fd = fd as usize;
+        assert_eq!(write(fd, &data), data.len() as isize);
+        assert_eq!(write(fd, &data), data.len() as isize);
+        close(fd);
+
+        let fd = open(&path, OpenFlags::RDONLY) as usize;
+        let mut buffer = [0u8; 3000];
+        let mut total = 0;
+        loop {
+            let len = read(fd, &mut buffer);
+            assert!(len >= 0);
+            if len == 0 {
+                break;
+            }
+            for (j, &byte) in buffer[..len as usize].iter().enumerate() {
+                assert_eq!(byte, data[(total + j) % data.len()]);
+            }
+            total += len as usize;
+        }
+        assert_eq!(total, 2 * data.len());
+        close(fd);
+    }
+
+    // truncating drops the old contents
+    let fd = open("/tmp/scratch0\0", OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 16];
+    assert_eq!(read(fd, &mut buffer), 0);
+
+    // writes past the size limit fail without growing the file
+    assert_eq!(pwrite(fd, &data, 1 << 30), -ENOSPC);
+    assert_eq!(pwrite(fd, &data, usize::MAX - 10), -ENOSPC);
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.size, 0);
+    close(fd);
+
+    assert_eq!(open("/tmp/missing\0", OpenFlags::RDONLY), -ENOENT);
+    assert_eq!(open("/tmp/scratch0/file\0", OpenFlags::CREATE), -ENOTDIR);
+    println!("tmpfstest passed!");
+    0
+}
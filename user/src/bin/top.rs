@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{get_time, proc_field, proc_pids, read_to_string, sleep};
+
+const INTERVAL_MS: usize = 1000;
+
+struct Sample {
+    pid: usize,
+    name: String,
+    state: String,
+    run_time: usize,
+}
+
+fn sample() -> Vec<Sample> {
+    proc_pids()
+        .into_iter()
+        .filter_map(|pid| {
+            let status = read_to_string(&format!("/proc/{}/status\0", pid))?;
+            let field = |key| proc_field(&status, key).unwrap_or("?");
+            Some(Sample {
+                pid,
+                name: String::from(field("Name")),
+                state: String::from(field("State")),
+                run_time: field("RunTimeMs").parse().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Usage: top [iterations], refreshing once per second.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let iterations = if argc > 1 {
+        match argv[1].parse() {
+            Ok(iterations) => iterations,
+            Err(_) => {
+                println!("usage: top [iterations]");
+                return -1;
+            }
+        }
+    } else {
+        5
+    };
+    let mut last = sample();
+    let mut last_time = get_time() as usize;
+    for _ in 0..iterations {
+        sleep(INTERVAL_MS);
+        let now = sample();
+        let now_time = get_time() as usize;
+        let elapsed = (now_time - last_time).max(1);
+        // processes that just appeared are charged for their whole run time
+        let mut rows: Vec<(usize, &Sample)> = now
+            .iter()
+            .map(|s| {
+                let before = last
+                    .iter()
+                    .find(|l| l.pid == s.pid)
+                    .map_or(0, |l| l.run_time);
+                (s.run_time.saturating_sub(before) * 100 / elapsed, s)
+            })
+            .collect();
+        rows.sort_by(|a, b| b.0.cmp(&a.0));
+        let uptime = read_to_string("/proc/uptime\0").unwrap_or_default();
+        println!("up {} s, {} processes", uptime.trim(), rows.len());
+        println!("{:>5} {:<9} {:>4} CMD", "PID", "STATE", "%CPU");
+        for (cpu, s) in rows {
+            println!("{:>5} {:<9} {:>4} {}", s.pid, s.state, cpu, s.name);
+        }
+        println!("");
+        last = now;
+        last_time = now_time;
+    }
+    0
+}
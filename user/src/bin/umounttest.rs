@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::EBUSY;
+use user_lib::{chdir, close, mount, open, read, umount, unlink, write, OpenFlags};
+
+/// Needs the scratch disk made by `make run` as /dev/vdb.
+#[no_mangle]
+pub fn main() -> i32 {
+    let path = "/mnt/umounttest\0";
+    assert_eq!(mount("/dev/vdb\0", "/mnt\0", "easy-fs\0"), 0);
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"kept"), 4);
+
+    // an open file or a current directory keeps the filesystem mounted
+    assert_eq!(umount("/mnt\0"), -EBUSY);
+    close(fd as usize);
+    assert_eq!(chdir("/mnt\0"), 0);
+    assert_eq!(umount("/mnt\0"), -EBUSY);
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(umount("/mnt\0"), 0);
+
+    assert_eq!(mount("/dev/vdb\0", "/mnt\0", "easy-fs\0"), 0);
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd as usize, &mut buf), 4);
+    assert_eq!(&buf[..4], b"kept");
+    close(fd as usize);
+    assert_eq!(unlink(path), 0);
+    assert_eq!(umount("/mnt\0"), 0);
+    println!("umounttest passed!");
+    0
+}
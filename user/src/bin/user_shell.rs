@@ -13,10 +13,12 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 const LINE_START: &str = ">> ";
 
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::errno::Errno;
+use user_lib::{chdir, close, dup2, exec, fork, open, pipe, waitpid, OpenFlags};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -74,6 +76,15 @@ impl ProcessArguments {
     }
 }
 
+/// The directory of a `cd` line, "/" if none is given.
+fn cd_target(line: &str) -> Option<&str> {
+    let mut words = line.split(' ').filter(|word| !word.is_empty());
+    match words.next() {
+        Some("cd") => Some(words.next().unwrap_or("/")),
+        _ => None,
+    }
+}
+
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
@@ -84,7 +95,14 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
+                if let Some(dir) = cd_target(line.as_str()) {
+                    // a builtin, a child could not change our directory
+                    let ret = chdir(format!("{}\0", dir).as_str());
+                    if ret < 0 {
+                        println!("cd: {}: {}", dir, Errno(-ret));
+                    }
+                    line.clear();
+                } else if !line.is_empty() {
                     let splited: Vec<_> = line.as_str().split('|').collect();
                     let process_arguments_list: Vec<_> = splited
                         .iter()
@@ -131,50 +149,61 @@ pub fn main() -> i32 {
                                 // redirect input
                                 if !input.is_empty() {
                                     let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
+                                    if input_fd < 0 {
+                                        println!(
+                                            "Error when opening file {}: {}",
+                                            input,
+                                            Errno(-input_fd)
+                                        );
                                         return -4;
                                     }
                                     let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
+                                    assert_eq!(dup2(input_fd, 0), 0);
                                     close(input_fd);
                                 }
                                 // redirect output
                                 if !output.is_empty() {
                                     let output_fd = open(
                                         output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
+                                        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
                                     );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
+                                    if output_fd < 0 {
+                                        println!(
+                                            "Error when opening file {}: {}",
+                                            output,
+                                            Errno(-output_fd)
+                                        );
                                         return -4;
                                     }
                                     let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
+                                    assert_eq!(dup2(output_fd, 1), 1);
                                     close(output_fd);
                                 }
                                 // receive input from the previous process
                                 if i > 0 {
-                                    close(0);
                                     let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
+                                    assert_eq!(dup2(read_end, 0), 0);
                                 }
                                 // send output to the next process
                                 if i < process_arguments_list.len() - 1 {
-                                    close(1);
                                     let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
+                                    assert_eq!(dup2(write_end, 1), 1);
                                 }
                                 // close all pipe ends inherited from the parent process
                                 for pipe_fd in pipes_fd.iter() {
                                     close(pipe_fd[0]);
                                     close(pipe_fd[1]);
                                 }
+                                // programs without a directory are found in the root
+                                let path = if args_copy[0].contains('/') {
+                                    args_copy[0].clone()
+                                } else {
+                                    format!("/{}", args_copy[0])
+                                };
                                 // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
+                                let ret = exec(path.as_str(), args_addr.as_slice());
+                                if ret < 0 {
+                                    println!("Error when executing: {}", Errno(-ret));
                                     return -4;
                                 }
                                 unreachable!();
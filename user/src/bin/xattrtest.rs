@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EEXIST, EINVAL, ENODATA};
+use user_lib::{close, getxattr, listxattr, open, removexattr, setxattr, OpenFlags, XattrFlags};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let file = "xattr_file\0";
+    let name = "user.origin\0";
+    let value = "xattrtest";
+    let fd = open(file, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    assert_eq!(
+        setxattr(file, name, value.as_bytes(), XattrFlags::REPLACE),
+        -ENODATA
+    );
+    assert_eq!(
+        setxattr(
+            file,
+            name,
+            value.as_bytes(),
+            XattrFlags::CREATE | XattrFlags::REPLACE
+        ),
+        -EINVAL
+    );
+    assert_eq!(
+        setxattr(file, name, value.as_bytes(), XattrFlags::CREATE),
+        0
+    );
+    assert_eq!(
+        setxattr(file, name, value.as_bytes(), XattrFlags::CREATE),
+        -EEXIST
+    );
+    assert_eq!(
+        setxattr(file, name, value.as_bytes(), XattrFlags::REPLACE),
+        0
+    );
+
+    let mut buffer = [0u8; 32];
+    assert_eq!(getxattr(file, name, &mut []), value.len() as isize);
+    let len = getxattr(file, name, &mut buffer) as usize;
+    assert_eq!(value, core::str::from_utf8(&buffer[..len]).unwrap());
+
+    let len = listxattr(file, &mut buffer) as usize;
+    assert_eq!(name.as_bytes(), &buffer[..len]);
+
+    assert_eq!(removexattr(file, name), 0);
+    assert_eq!(getxattr(file, name, &mut buffer), -ENODATA);
+    assert_eq!(listxattr(file, &mut buffer), 0);
+    println!("xattrtest passed!");
+    0
+}
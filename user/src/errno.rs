@@ -0,0 +1,76 @@
+//! Linux errnos, returned negated by failing system calls.
+
+use core::fmt;
+
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EIO: isize = 5;
+pub const ENOEXEC: isize = 8;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const EBUSY: isize = 16;
+pub const EEXIST: isize = 17;
+pub const ENODEV: isize = 19;
+pub const ENOTDIR: isize = 20;
+pub const EISDIR: isize = 21;
+pub const EINVAL: isize = 22;
+pub const ENOSPC: isize = 28;
+pub const ESPIPE: isize = 29;
+pub const EROFS: isize = 30;
+pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
+pub const ENOSYS: isize = 38;
+pub const ENOTEMPTY: isize = 39;
+pub const ENODATA: isize = 61;
+pub const EOPNOTSUPP: isize = 95;
+pub const EUCLEAN: isize = 117;
+
+/// The error of a failed system call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Errno(pub isize);
+
+impl Errno {
+    /// Split the return value of a system call into its result or error.
+    pub fn decode(ret: isize) -> Result<usize, Errno> {
+        if ret < 0 {
+            Err(Errno(-ret))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+    pub fn description(self) -> &'static str {
+        match self.0 {
+            ENOENT => "No such file or directory",
+            ESRCH => "No such process",
+            EIO => "Input/output error",
+            ENOEXEC => "Exec format error",
+            EBADF => "Bad file descriptor",
+            ECHILD => "No child processes",
+            EAGAIN => "Resource temporarily unavailable",
+            EBUSY => "Device or resource busy",
+            EEXIST => "File exists",
+            ENODEV => "No such device",
+            ENOTDIR => "Not a directory",
+            EISDIR => "Is a directory",
+            EINVAL => "Invalid argument",
+            ENOSPC => "No space left on device",
+            ESPIPE => "Illegal seek",
+            EROFS => "Read-only file system",
+            ERANGE => "Numerical result out of range",
+            EDEADLK => "Resource deadlock avoided",
+            ENOSYS => "Function not implemented",
+            ENOTEMPTY => "Directory not empty",
+            ENODATA => "No data available",
+            EOPNOTSUPP => "Operation not supported",
+            EUCLEAN => "Structure needs cleaning",
+            _ => "Unknown error",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (errno {})", self.description(), self.0)
+    }
+}
@@ -5,6 +5,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod errno;
 mod lang_items;
 mod syscall;
 
@@ -12,8 +13,11 @@ extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::convert::TryInto;
+use errno::EAGAIN;
 use syscall::*;
 
 const USER_HEAP_SIZE: usize = 32768;
@@ -63,16 +67,170 @@ bitflags! {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
-        const CREATE = 1 << 9;
-        const TRUNC = 1 << 10;
+        const CREATE = 1 << 6;
+        const EXCL = 1 << 7;
+        const TRUNC = 1 << 9;
+        const APPEND = 1 << 10;
+        const DIRECTORY = 1 << 16;
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// Laid out like `struct stat` of Linux on riscv64. Fields the filesystem
+/// can not tell are 0, times are seconds since the epoch.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    /// File type and permission bits.
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    /// In 512 byte units.
+    pub blocks: i64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+/// `DirEntry::d_type` values.
+pub const DT_FIFO: u8 = 1;
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+/// One record written by `getdents`.
+pub struct DirEntry<'a> {
+    pub ino: u64,
+    /// Pass to `lseek` to continue after this entry.
+    pub off: i64,
+    pub d_type: u8,
+    pub name: &'a str,
+}
+
+/// The records `getdents` wrote at the start of a buffer.
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DirEntries<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = DirEntry<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < 19 {
+            return None;
+        }
+        let reclen = u16::from_ne_bytes(self.buf[16..18].try_into().unwrap()) as usize;
+        let name = &self.buf[19..reclen];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        let entry = DirEntry {
+            ino: u64::from_ne_bytes(self.buf[..8].try_into().unwrap()),
+            off: i64::from_ne_bytes(self.buf[8..16].try_into().unwrap()),
+            d_type: self.buf[18],
+            name: core::str::from_utf8(&name[..name_len]).unwrap_or("?"),
+        };
+        self.buf = &self.buf[reclen..];
+        Some(entry)
+    }
+}
+
+bitflags! {
+    pub struct XattrFlags: u32 {
+        const CREATE = 1;
+        const REPLACE = 2;
     }
 }
 
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+/// Like `dup3`, but `oldfd` may be `newfd`, which is then only checked.
+pub fn dup2(oldfd: usize, newfd: usize) -> isize {
+    if oldfd == newfd {
+        let ret = fstat(oldfd, &mut Stat::default());
+        return if ret < 0 { ret } else { newfd as isize };
+    }
+    sys_dup3(oldfd, newfd, 0)
+}
+/// Only `OpenFlags::CLOEXEC` may be given.
+pub fn dup3(oldfd: usize, newfd: usize, flags: OpenFlags) -> isize {
+    sys_dup3(oldfd, newfd, flags.bits())
+}
+/// Relative paths of the `*at` calls start from the current directory.
+pub const AT_FDCWD: isize = -100;
+pub const AT_REMOVEDIR: u32 = 0x200;
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
-    sys_open(path, flags.bits)
+    sys_openat(AT_FDCWD, path, flags.bits)
+}
+/// Like `open`, but a relative `path` starts from the directory `dirfd`.
+pub fn openat(dirfd: isize, path: &str, flags: OpenFlags) -> isize {
+    sys_openat(dirfd, path, flags.bits)
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD, path, 0o755)
+}
+pub fn mkdirat(dirfd: isize, path: &str) -> isize {
+    sys_mkdirat(dirfd, path, 0o755)
+}
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, 0)
+}
+/// The directory must be empty.
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, AT_REMOVEDIR)
+}
+/// Remove a file, or an empty directory if `flags` has `AT_REMOVEDIR`.
+pub fn unlinkat(dirfd: isize, path: &str, flags: u32) -> isize {
+    sys_unlinkat(dirfd, path, flags)
+}
+/// Write the current directory and a '\0', return their length.
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+/// `fd` must be an open directory.
+pub fn fchdir(fd: usize) -> isize {
+    sys_fchdir(fd)
 }
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
@@ -86,6 +244,121 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// Return the new offset.
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+/// Read at `offset` without moving the file offset.
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    sys_pread(fd, buf, offset)
+}
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> isize {
+    sys_pwrite(fd, buf, offset)
+}
+/// Fill `buf` with directory entries, read them with `DirEntries`.
+/// Return 0 at the end of the directory.
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+/// Names in the directory `path`, `None` if it can not be listed.
+pub fn list_dir(path: &str) -> Option<Vec<String>> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut names = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents(fd, &mut buf);
+        if len <= 0 {
+            close(fd);
+            return if len == 0 { Some(names) } else { None };
+        }
+        for entry in DirEntries::new(&buf[..len as usize]) {
+            names.push(String::from(entry.name));
+        }
+    }
+}
+/// The whole file `path` as text, `None` if it can not be read.
+pub fn read_to_string(path: &str) -> Option<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut content = Vec::new();
+    let mut buf = [0u8; 256];
+    let len = loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break len;
+        }
+        content.extend_from_slice(&buf[..len as usize]);
+    };
+    close(fd);
+    if len < 0 {
+        return None;
+    }
+    String::from_utf8(content).ok()
+}
+/// The value of the "Key:\tvalue" line in a /proc file such as status.
+pub fn proc_field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .map(str::trim)
+}
+/// Live processes in ascending order, the numeric entries of /proc.
+pub fn proc_pids() -> Vec<usize> {
+    let mut pids: Vec<usize> = list_dir("/proc\0")
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+pub fn fstat(fd: usize, stat: &mut Stat) -> isize {
+    sys_fstat(fd, stat)
+}
+
+pub fn stat(path: &str, stat: &mut Stat) -> isize {
+    sys_fstatat(AT_FDCWD, path, stat, 0)
+}
+/// Like `stat`, but describe a symlink itself rather than its target.
+pub fn lstat(path: &str, stat: &mut Stat) -> isize {
+    sys_fstatat(AT_FDCWD, path, stat, AT_SYMLINK_NOFOLLOW)
+}
+/// Like `stat`, but a relative `path` starts from the directory `dirfd`.
+pub fn statat(dirfd: isize, path: &str, stat: &mut Stat) -> isize {
+    sys_fstatat(dirfd, path, stat, 0)
+}
+pub fn setxattr(path: &str, name: &str, value: &[u8], flags: XattrFlags) -> isize {
+    sys_setxattr(path, name, value, flags.bits)
+}
+/// Return the size of the value, only query the size if `value` is empty.
+pub fn getxattr(path: &str, name: &str, value: &mut [u8]) -> isize {
+    sys_getxattr(path, name, value)
+}
+/// Names are separated by '\0', only query the size if `list` is empty.
+pub fn listxattr(path: &str, list: &mut [u8]) -> isize {
+    sys_listxattr(path, list)
+}
+pub fn removexattr(path: &str, name: &str) -> isize {
+    sys_removexattr(path, name)
+}
+/// `source` names a block device like "/dev/vdb", `fstype` e.g. "easy-fs".
+pub fn mount(source: &str, target: &str, fstype: &str) -> isize {
+    sys_mount(source, target, fstype, 0)
+}
+pub fn umount(target: &str) -> isize {
+    sys_umount2(target, 0)
+}
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
@@ -107,10 +380,10 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
+            ret if ret == -EAGAIN => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD or a real pid
             exit_pid => return exit_pid,
         }
     }
@@ -119,10 +392,10 @@ pub fn wait(exit_code: &mut i32) -> isize {
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
+            ret if ret == -EAGAIN => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD or a real pid
             exit_pid => return exit_pid,
         }
     }
@@ -156,13 +429,14 @@ pub fn thread_create(entry: usize, arg: usize) -> isize {
 pub fn gettid() -> isize {
     sys_gettid()
 }
-pub fn waittid(tid: usize) -> isize {
+pub fn waittid(tid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waittid(tid) {
-            -2 => {
+        match sys_waittid(tid, exit_code as *mut _) {
+            ret if ret == -EAGAIN => {
                 yield_();
             }
-            exit_code => return exit_code,
+            // -ESRCH, -EDEADLK or the tid
+            exit_tid => return exit_tid,
         }
     }
 }